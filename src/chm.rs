@@ -8,6 +8,7 @@
 mod config;
 pub use config::*;
 
+pub mod html;
 pub mod inputs;
 pub mod keyworder;
 pub mod utilities;
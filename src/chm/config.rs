@@ -1,16 +1,29 @@
 use hhc::ChmContentsEntry;
-use hhk::{ChmIndex, ChmIndexEntry};
+use hhk::{ChmIndex, ChmIndexEntry, ChmIndexTopic};
+use popups::{ChmTextPopup, ChmTextPopups};
 
-use crate::chm::keyworder::Keyworder;
+use crate::chm::{
+    inputs::{Confinement, InputOptions},
+    keyworder::{CodeIndexMode, KeywordSettings, Keyworder, MarkerMode},
+};
 
-use super::utilities::{MakeAbsolute, SafeWrite, escape_html, find_compiler};
-use std::path::{Path, PathBuf};
+use super::utilities::{
+    MakeAbsolute, SafeWrite, escape_html, find_compiler, normalize_path, relative_url,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
+pub mod alinks;
+pub mod anchors;
 pub mod contents;
+pub mod curated;
 
 pub mod hhc;
 pub mod hhk;
 pub mod hhp;
+pub mod popups;
 
 mod language;
 pub use language::ChmLanguage;
@@ -22,6 +35,13 @@ pub use language::ChmLanguage;
 pub struct ChmBuilder {
     project: hhp::ChmProject,
     contents: hhc::ChmContents,
+    popups: ChmTextPopups,
+    index: ChmIndex,
+    blocked_keywords: HashSet<String>,
+    keywords: KeywordSettings,
+    related_topics: alinks::RelatedTopics,
+    dead_anchors: anchors::DeadAnchors,
+    confinement: Option<Confinement>,
     project_path: PathBuf,
     working_dir: PathBuf,
 }
@@ -49,40 +69,205 @@ impl ChmBuilder {
             index_path: index_path.to_windows_path(),
             contents_path: contents_path.to_windows_path(),
             default_file: String::new(),
+            options: hhp::ChmOptions::default(),
+            popups_path: None,
+            context_header_path: None,
+            files: vec![],
         };
-        let contents = hhc::ChmContents(vec![]);
+        let contents = hhc::ChmContents::default();
 
         Self {
             project,
             contents,
+            popups: ChmTextPopups::default(),
+            index: ChmIndex::new(),
+            blocked_keywords: HashSet::new(),
+            keywords: KeywordSettings::default(),
+            related_topics: alinks::RelatedTopics::default(),
+            dead_anchors: anchors::DeadAnchors::default(),
+            confinement: None,
             project_path,
             working_dir,
         }
     }
 
-    /// Include a topic in the CHM file.  
+    /// Override the compiler options written to the project file.\
+    /// Relative paths in the options are resolved against the current directory
+    pub fn with_options(&mut self, mut options: hhp::ChmOptions) -> &mut Self {
+        for path in [
+            &mut options.error_log_file,
+            &mut options.full_text_search_stop_list_file,
+            &mut options.sample_list_file,
+            &mut options.sample_staging_path,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = Path::new(path).make_absolute().to_windows_path();
+        }
+
+        self.project.options = options;
+        self
+    }
+
+    /// Set the appearance of the TOC pane
+    pub fn with_contents_properties(
+        &mut self,
+        properties: hhc::ChmContentsProperties,
+    ) -> &mut Self {
+        self.contents.properties = properties;
+        self
+    }
+
+    /// Control which extracted keywords are added to the index
+    pub fn with_keyword_settings(&mut self, settings: KeywordSettings) -> &mut Self {
+        self.keywords = settings;
+        self
+    }
+
+    /// Include an entry in the index, alongside the TOC titles and extracted keywords.\
+    /// Entries sharing a keyword are merged
+    pub fn with_index_entry(&mut self, entry: ChmIndexEntry) -> &mut Self {
+        self.index.insert(entry);
+        self
+    }
+
+    /// Keep a keyword out of the index, wherever it came from
+    pub fn with_blocked_keyword(&mut self, keyword: &impl ToString) -> &mut Self {
+        self.blocked_keywords
+            .insert(escape_html(&keyword.to_string()).to_lowercase());
+        self
+    }
+
+    /// Set how topics show the other members of their `ALink` groups
+    pub fn with_related_topics(&mut self, related_topics: alinks::RelatedTopics) -> &mut Self {
+        self.related_topics = related_topics;
+        self
+    }
+
+    /// Set what happens to links pointing at anchors missing from their topic
+    pub fn with_dead_anchors(&mut self, dead_anchors: anchors::DeadAnchors) -> &mut Self {
+        self.dead_anchors = dead_anchors;
+        self
+    }
+
+    /// Set where files named in the settings, like the TOC image list, can be read from.\
+    /// Without it, any path is read
+    pub fn with_confinement(&mut self, confinement: Confinement) -> &mut Self {
+        self.confinement = Some(confinement);
+        self
+    }
+
+    /// Include a text popup in the CHM file.\
+    /// Popups declared inside topics are picked up automatically
+    pub fn with_popup(&mut self, popup: ChmTextPopup) -> &mut Self {
+        self.popups.0.push(popup);
+        self
+    }
+
+    /// Include a topic in the CHM file.\
     /// Topics can nest deeply.
     pub fn with_contents(&mut self, topic: ChmTopicBuilder) -> &mut Self {
-        if self.project.default_file.is_empty() {
-            if let Some(last) = topic.0.files.last() {
-                self.project.default_file = last.path.to_windows_path();
-            }
+        if self.project.default_file.is_empty()
+            && let Some(page) = topic.0.first_page()
+        {
+            self.project.default_file = page.path.to_windows_path();
         }
 
-        self.contents.0.push(topic.0);
+        self.contents.entries.push(topic.0);
         self
     }
 
-    /// Writes the CHM project component files to the specified output paths.  
+    /// Writes the CHM project component files to the specified output paths.\
     /// Does NOT compile the CHM file.
     ///
     /// # Errors
     /// Can return an error if output writes fail
     pub fn write(&self) -> std::io::Result<()> {
+        let mut project = self.project.clone();
+
+        //
+        // Flatten TOC to finish building the project files
+        let flat_map = self.contents.clone().flatten();
+        let files = flat_map
+            .iter()
+            .flat_map(|entry| entry.files.clone())
+            .collect::<Vec<_>>();
+
+        //
+        // Check the links between topics before writing anything
+        self.dead_anchors.check(&files)?;
+
+        //
+        // Files must stay inside the working directory
+        if let Some(file) = files
+            .iter()
+            .find(|file| file.path.is_absolute() || normalize_path(&file.path).is_none())
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!(
+                    "`{}` would be written outside of the project",
+                    file.path.display()
+                ),
+            ));
+        }
+
+        //
+        // Write text popups, and the header exported alongside the CHM
+        let mut popups = self.popups.clone();
+        popups.0.extend(
+            files
+                .iter()
+                .flat_map(|file| file.metadata.popups.iter().cloned()),
+        );
+        if !popups.0.is_empty() {
+            popups.assign_contexts()?;
+
+            let popups_path = self.working_dir.join("popups.txt");
+            println!("Writing {}", popups_path.display());
+            popups_path.safe_write(popups.to_string().as_bytes())?;
+
+            let header = popups.header();
+            let header_path = self.working_dir.join("context.h");
+            println!("Writing {}", header_path.display());
+            header_path.safe_write(header.as_bytes())?;
+
+            let export_path = PathBuf::from(&project.output_path).with_extension("h");
+            println!("Writing {}", export_path.display());
+            export_path.safe_write(header.as_bytes())?;
+
+            project.popups_path = Some(popups_path.to_windows_path());
+            project.context_header_path = Some(header_path.to_windows_path());
+        }
+
+        //
+        // Copy the custom TOC icons into the project
+        self.copy_image_list(&files, &mut project)?;
+
+        //
+        // Keep the stop words out of full-text search as well
+        if project.options.full_text_search_stop_list_file.is_none()
+            && !self.keywords.stop_words.is_empty()
+        {
+            let stop_list_path = self.working_dir.join("stopwords.stp");
+            let stop_list = self
+                .keywords
+                .stop_words
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join("\n");
+            println!("Writing {}", stop_list_path.display());
+            stop_list_path.safe_write(stop_list.as_bytes())?;
+            project.options.full_text_search_stop_list_file =
+                Some(stop_list_path.to_windows_path());
+        }
+
         //
         // Write project file
         let project_path = &self.project_path;
-        let project = self.project.to_string();
+        let project = project.to_string();
         println!("Writing {}", project_path.display());
         project_path.safe_write(project.as_bytes())?;
 
@@ -94,56 +279,272 @@ impl ChmBuilder {
         contents_path.safe_write(contents.as_bytes())?;
 
         //
-        // Flatten TOC to finish building the project files
-        let flat_map = self.contents.clone().flatten();
-        let mut index = ChmIndex(
-            flat_map
+        // Build the index from the TOC titles, marked terms and keywords
+        let titles = topic_titles(&flat_map);
+        let index = self.build_index(&flat_map, &files, &titles);
+
+        //
+        // Write index
+        let index_path = PathBuf::from(self.project.index_path.clone());
+        let index = index.to_string();
+        println!("Writing {}", index_path.display());
+        index_path.safe_write(index.as_bytes())?;
+
+        //
+        // Write dependencies
+        for file in &files {
+            let target_path = self.working_dir.join(&file.path);
+            match file.str_contents() {
+                Some(html) => {
+                    let html = self.fill_related_topics(file, html, &files, &titles);
+                    target_path.safe_write(html.as_bytes())?;
+                }
+                None => target_path.safe_write(&file.contents)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies the custom TOC icons to the root of the project, reading them like other dependencies
+    fn copy_image_list(
+        &self,
+        files: &[contents::File],
+        project: &mut hhp::ChmProject,
+    ) -> std::io::Result<()> {
+        if let Some(image_list) = &self.contents.properties.image_list {
+            let dependency = Path::new(image_list);
+            let source = match &self.confinement {
+                Some(confinement) => confinement.source(
+                    &confinement
+                        .mount(dependency)
+                        .unwrap_or_else(|| dependency.to_path_buf()),
+                )?,
+                None => dependency.to_path_buf(),
+            };
+
+            //
+            // The image list sits at the root of the project, where it could replace a dependency
+            let name = Path::new(dependency.file_name().unwrap_or(dependency.as_os_str()));
+            let target = self.working_dir.join(name);
+            if files
                 .iter()
-                .map(|entry| ChmIndexEntry {
-                    keyword: escape_html(&entry.title),
-                    file: escape_html(&entry.file),
-                })
-                .collect(),
-        );
-        let files = flat_map
-            .iter()
-            .flat_map(|entry| entry.files.clone())
-            .collect::<Vec<_>>();
+                .any(|file| normalize_path(&file.path) == normalize_path(name))
+                || project.files.contains(&target.to_windows_path())
+            {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    format!(
+                        "The TOC image list `{image_list}` would overwrite `{}` in the project",
+                        name.display()
+                    ),
+                ));
+            }
+
+            println!("Writing {}", target.display());
+            target.safe_copy(source)?;
+            project.files.push(target.to_windows_path());
+        }
+        Ok(())
+    }
+
+    /// Builds the index from the added entries, the TOC titles, the terms marked by authors, and the extracted keywords
+    fn build_index(
+        &self,
+        flat_map: &[ChmContentsEntry],
+        files: &[contents::File],
+        titles: &HashMap<String, String>,
+    ) -> ChmIndex {
+        //
+        // Start with the TOC titles
+        let mut index = self.index.clone();
+        for entry in flat_map.iter().filter(|entry| !entry.file.is_empty()) {
+            let title = &titles[&entry.file];
+            index.insert(ChmIndexEntry::new(
+                title,
+                ChmIndexTopic::new(title, &escape_html(&entry.file)),
+            ));
+        }
+
+        //
+        // Add the terms marked by the authors and the headings inside each topic
+        for file in files {
+            let path = file.path.to_windows_path();
+            let title = titles
+                .get(&path)
+                .cloned()
+                .unwrap_or_else(|| escape_html(&path));
+            index.extend(self.anchored_entries(file, &path, &title));
+        }
+
+        //
+        // Add the identifiers found in code
+        index.extend(self.code_entries(files, titles));
 
         //
         // Enhance the index with extra keywords
         println!("Extracting keywords from files...");
-        let mut keyworder = Keyworder::new();
-        for entry in &files {
+        let mut keyworder = Keyworder::with_settings(self.keywords.clone());
+        for entry in files {
+            if self.keywords.markers == MarkerMode::Replace
+                && !entry.metadata.index_markers.is_empty()
+            {
+                continue;
+            }
             if let Some(str) = entry.str_contents() {
                 keyworder.process(&entry.path, str);
             }
         }
         for keyword in keyworder.visible_keywords() {
-            for path in &keyword.seen_in {
-                let path = path.to_string_lossy();
-                index.0.push(ChmIndexEntry {
-                    keyword: escape_html(keyword.keyword),
-                    file: escape_html(path.as_ref()),
-                });
+            for sighting in &keyword.seen_in {
+                let path = sighting.path.to_windows_path();
+                let title = titles
+                    .get(&path)
+                    .cloned()
+                    .unwrap_or_else(|| escape_html(&path));
+                index.insert(ChmIndexEntry::new(
+                    &escape_html(&keyword.keyword),
+                    ChmIndexTopic::new(&title, &escape_html(&path)),
+                ));
             }
         }
 
         //
-        // Write index
-        let index_path = PathBuf::from(self.project.index_path.clone());
-        let index = index.to_string();
-        println!("Writing {}", index_path.display());
-        index_path.safe_write(index.as_bytes())?;
+        // Blocked keywords go last, so they are removed wherever they came from
+        index.remove_keywords(&self.blocked_keywords);
+        index
+    }
 
-        //
-        // Write dependencies
-        for file in &files {
-            let target_path = self.working_dir.join(&file.path);
-            target_path.safe_write(&file.contents)?;
+    /// Index entries pointing at anchors inside a topic: terms marked by the authors, and headings
+    fn anchored_entries(
+        &self,
+        file: &contents::File,
+        path: &str,
+        title: &str,
+    ) -> Vec<ChmIndexEntry> {
+        let mut entries = vec![];
+        for marker in &file.metadata.index_markers {
+            let terms = marker
+                .terms
+                .iter()
+                .map(|term| escape_html(term))
+                .collect::<Vec<_>>();
+            let file = escape_html(&format!("{path}#{}", marker.anchor));
+            let Some((last, parents)) = terms.split_last() else {
+                continue;
+            };
+            let entry = match &marker.see_also {
+                Some(see_also) => ChmIndexEntry::see_also(last, &escape_html(see_also)),
+                None => ChmIndexEntry::new(last, ChmIndexTopic::new(&title, &file)),
+            };
+            entries.push(entry.with_parents(parents));
         }
 
-        Ok(())
+        let headings = &self.keywords.headings;
+        for heading in &file.metadata.headings {
+            let anchored = format!("{path}#{}", heading.id);
+            if heading.level < 2
+                || heading.level > headings.max_level
+                || heading.title.is_empty()
+                || headings.exclude.contains(&heading.title.to_lowercase())
+                || headings.exclude.contains(&anchored.to_lowercase())
+            {
+                continue;
+            }
+
+            let topic = ChmIndexTopic::new(&title, &escape_html(&anchored));
+            let entry = ChmIndexEntry::new(&escape_html(&heading.title), topic);
+            entries.push(if headings.nested {
+                entry.with_parents(&[title])
+            } else {
+                entry
+            });
+        }
+
+        entries
+    }
+
+    /// Index entries for the Rust identifiers found in code, grouped according to the settings.\
+    /// Identifiers point at the code defining them, or where they are first mentioned if they are never defined
+    fn code_entries(
+        &self,
+        files: &[contents::File],
+        titles: &HashMap<String, String>,
+    ) -> Vec<ChmIndexEntry> {
+        let settings = &self.keywords.code;
+        if settings.mode == CodeIndexMode::Off {
+            return vec![];
+        }
+
+        let mut definitions: BTreeMap<&str, Vec<ChmIndexTopic>> = BTreeMap::new();
+        let mut mentions: BTreeMap<&str, ChmIndexTopic> = BTreeMap::new();
+        for file in files {
+            let path = file.path.to_windows_path();
+            let title = titles
+                .get(&path)
+                .cloned()
+                .unwrap_or_else(|| escape_html(&path));
+            for identifier in &file.metadata.code_identifiers {
+                let topic = ChmIndexTopic::new(
+                    &title,
+                    &escape_html(&format!("{path}#{}", identifier.anchor)),
+                );
+                if identifier.defined {
+                    definitions.entry(&identifier.name).or_default().push(topic);
+                } else {
+                    mentions.entry(&identifier.name).or_insert(topic);
+                }
+            }
+        }
+        for (name, topic) in mentions {
+            definitions.entry(name).or_insert_with(|| vec![topic]);
+        }
+
+        let mut entries = vec![];
+        for (name, topics) in definitions {
+            let (group, name) = settings.group(name);
+            let entry = ChmIndexEntry {
+                keyword: escape_html(name),
+                topics,
+                see_also: None,
+                children: vec![],
+            };
+            entries.push(entry.with_parents(&[escape_html(group)]));
+        }
+        entries
+    }
+
+    /// Replaces the related-topic placeholders in a topic with the other members of its `ALink` groups
+    fn fill_related_topics<'t>(
+        &self,
+        file: &contents::File,
+        html: &'t str,
+        files: &[contents::File],
+        titles: &HashMap<String, String>,
+    ) -> std::borrow::Cow<'t, str> {
+        self.related_topics
+            .fill(html, &file.metadata.alinks, |groups: &[String]| {
+                let mut seen = HashSet::new();
+                files
+                    .iter()
+                    .filter(|other| other.path != file.path && seen.insert(&other.path))
+                    .filter(|other| {
+                        other
+                            .metadata
+                            .alinks
+                            .iter()
+                            .any(|group| groups.contains(&escape_html(group)))
+                    })
+                    .map(|other| {
+                        let path = other.path.to_windows_path();
+                        let title = titles
+                            .get(&path)
+                            .cloned()
+                            .unwrap_or_else(|| escape_html(&path));
+                        (escape_html(&relative_url(&file.path, &other.path)), title)
+                    })
+                    .collect()
+            })
     }
 
     /// Writes the CHM project component files to the specified output paths and compiles the CHM file.
@@ -165,6 +566,15 @@ impl ChmBuilder {
     }
 }
 
+/// The escaped title of each page in the TOC, by path
+fn topic_titles(flat_map: &[ChmContentsEntry]) -> HashMap<String, String> {
+    flat_map
+        .iter()
+        .filter(|entry| !entry.file.is_empty())
+        .map(|entry| (entry.file.clone(), escape_html(&entry.title)))
+        .collect()
+}
+
 /// Create a CHM topic (chapter) based on an input file.
 ///
 /// Will pull any dependencies (images, stylesheets, etc),
@@ -195,6 +605,38 @@ impl ChmTopicBuilder {
         Ok(Self(topic))
     }
 
+    /// Build a topic based on a file, rendered with the given options
+    ///
+    /// # Errors
+    /// Will return an error on IO failures, or if the file references dead images
+    pub fn new_with_options(
+        title: &impl ToString,
+        file: impl AsRef<Path>,
+        content: &str,
+        options: InputOptions,
+    ) -> std::io::Result<Self> {
+        let topic = ChmContentsEntry::with_options(title, file, content, options)?;
+        Ok(Self(topic))
+    }
+
+    /// Build a topic without a page, which only groups its subtopics
+    #[must_use]
+    pub fn new_folder(title: &impl ToString) -> Self {
+        Self(ChmContentsEntry::folder(title))
+    }
+
+    /// Build a blank topic, used to add spacing between other topics
+    #[must_use]
+    pub fn new_separator() -> Self {
+        Self(ChmContentsEntry::separator())
+    }
+
+    /// Set the appearance of this topic in the TOC
+    pub fn with_properties(&mut self, properties: hhc::ChmEntryProperties) -> &mut Self {
+        self.0.properties = properties;
+        self
+    }
+
     /// Add a subtopic to this topic
     ///
     /// See [`ChmTopicBuilder::new`]
@@ -208,3 +650,232 @@ impl From<ChmTopicBuilder> for ChmContentsEntry {
         value.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(title: &str, path: &str, html: &str) -> (ChmContentsEntry, contents::File) {
+        let mut entry = ChmContentsEntry::folder(&title);
+        entry.file = path.to_string();
+        let file = contents::File {
+            path: PathBuf::from(path),
+            contents: html.as_bytes().to_vec(),
+            metadata: contents::FileMetadata {
+                alinks: vec!["Collections".to_string()],
+                ..Default::default()
+            },
+        };
+        (entry, file)
+    }
+
+    /// A temporary output directory, removed when dropped
+    struct Output(PathBuf);
+    impl Output {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("mdbook-chm-config-{name}-{}", std::process::id()));
+            std::fs::remove_dir_all(&dir).ok();
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn builder(&self) -> ChmBuilder {
+            ChmBuilder::new("Book", ChmLanguage::default(), self.0.join("book.chm"))
+        }
+    }
+    impl Drop for Output {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn related_topics_escape_titles() {
+        let builder = ChmBuilder::new("Book", ChmLanguage::default(), "book.chm");
+        let (own_entry, own_file) = topic("Strings", "a.html", "<!--chm-related-->");
+        let (other_entry, other_file) = topic("Vec<T> & Friends", "b.html", "");
+        let titles = topic_titles(&[own_entry, other_entry]);
+        let files = [own_file, other_file];
+
+        let html = builder.fill_related_topics(&files[0], "<!--chm-related-->", &files, &titles);
+        assert!(html.contains(r#"<a href="b.html">Vec&lt;T&gt; &amp; Friends</a>"#));
+    }
+
+    #[test]
+    fn related_topics_button_leaves_out_the_topic() {
+        let mut builder = ChmBuilder::new("Book", ChmLanguage::default(), "book.chm");
+        builder.with_related_topics(alinks::RelatedTopics {
+            style: alinks::RelatedTopicsStyle::Button,
+            title: "See also".to_string(),
+        });
+        let (own_entry, own_file) = topic("Strings", "a.html", "<!--chm-related-->");
+        let (vec_entry, vec_file) = topic("Vec", "b.html", "");
+        let (map_entry, mut map_file) = topic("HashMap", "c.html", "");
+        let (other_entry, mut other_file) = topic("Threads", "d.html", "");
+        map_file.metadata.alinks.push("Maps".to_string());
+        other_file.metadata.alinks = vec!["Concurrency".to_string()];
+        let titles = topic_titles(&[own_entry, vec_entry, map_entry, other_entry]);
+        let files = [own_file, vec_file, map_file, other_file];
+
+        let html = builder.fill_related_topics(&files[0], "<!--chm-related-->", &files, &titles);
+        assert_eq!(
+            html,
+            concat!(
+                r#"<OBJECT type="application/x-oleobject" classid="clsid:adb880a6-d8ff-11cf-9377-00aa003b7a11">"#,
+                r#"<param name="Command" value="Related Topics,MENU">"#,
+                r#"<param name="Button" value="Text:See also">"#,
+                r#"<param name="Item1" value="Vec;b.html">"#,
+                r#"<param name="Item2" value="HashMap;c.html">"#,
+                "</OBJECT>"
+            )
+        );
+
+        let html = builder.fill_related_topics(&files[3], "<!--chm-related-->", &files, &titles);
+        assert!(html.is_empty());
+    }
+
+    #[test]
+    fn image_list_is_confined_and_kept_apart() {
+        let output = Output::new("image-list");
+        std::fs::create_dir_all(output.0.join("book")).unwrap();
+        std::fs::write(output.0.join("secret.bmp"), "secret").unwrap();
+
+        let mut builder = output.builder();
+        builder.with_confinement(Confinement::new(
+            output.0.join("book"),
+            &[],
+            crate::chm::inputs::SymlinkPolicy::default(),
+        ));
+        builder.contents.properties.image_list = Some("../secret.bmp".to_string());
+        let error = builder.write().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+
+        let mut builder = output.builder();
+        let (mut entry, file) = topic("Icons", "icons.bmp", "");
+        entry.files.push(file);
+        builder.contents.entries.push(entry);
+        builder.contents.properties.image_list = Some("images/icons.bmp".to_string());
+        let error = builder.write().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+        assert!(!output.0.join("src").exists());
+    }
+
+    #[test]
+    fn index_holds_marked_terms() {
+        let builder = ChmBuilder::new("Book", ChmLanguage::default(), "book.chm");
+        let (entry, mut file) = topic("Borrowing", "b.html", "<p>Text</p>");
+        file.metadata.index_markers = vec![
+            contents::IndexMarker {
+                terms: vec!["Vec".to_string(), "push".to_string()],
+                anchor: "idx-1".to_string(),
+                see_also: None,
+            },
+            contents::IndexMarker {
+                terms: vec!["Lifetimes".to_string()],
+                anchor: "idx-2".to_string(),
+                see_also: Some("Borrowing".to_string()),
+            },
+        ];
+        let flat_map = [entry];
+        let titles = topic_titles(&flat_map);
+
+        let index = builder.build_index(&flat_map, &[file], &titles).to_string();
+        assert!(index.contains(
+            r#"<param name="Name" value="push">        <param name="Local" value="b.html#idx-1">"#
+        ));
+        assert!(index.contains(
+            r#"<param name="Name" value="Lifetimes">        <param name="See Also" value="Borrowing">"#
+        ));
+    }
+
+    #[test]
+    fn popups_are_unique_across_chapters() {
+        let output = Output::new("popups");
+        let write = |first: &str, second: &str| {
+            let mut builder = output.builder();
+            for (path, markdown) in [("a.md", first), ("b.md", second)] {
+                builder.with_contents(
+                    ChmTopicBuilder::new_with_content(&path, path, markdown).unwrap(),
+                );
+            }
+            builder.write().unwrap_err().to_string()
+        };
+
+        let error = write(
+            "```popup IDH_SAVE\nSaves\n```\n",
+            "```popup IDH_SAVE\nAlso saves\n```\n",
+        );
+        assert!(error.contains("popup ID `IDH_SAVE` is used more than once"));
+
+        let error = write(
+            "```popup IDH_SAVE 7\nSaves\n```\n",
+            "```popup IDH_OPEN 7\nOpens\n```\n",
+        );
+        assert!(error.contains("popup context number `7` is used more than once"));
+        assert!(!output.0.join("src").exists());
+    }
+
+    #[test]
+    fn index_holds_headings() {
+        let heading = |level, id: &str, title: &str| contents::Heading {
+            level,
+            id: id.to_string(),
+            title: title.to_string(),
+        };
+        let (entry, mut file) = topic("Collections", "src\\c.html", "<p>Text</p>");
+        file.metadata.headings = vec![
+            heading(1, "collections", "Collections"),
+            heading(2, "vec", "Vec<T>"),
+            heading(3, "capacity", "Capacity"),
+            heading(4, "details", "Details"),
+            heading(2, "summary", "Summary"),
+            heading(2, "notes", "Notes"),
+        ];
+        let flat_map = [entry];
+        let titles = topic_titles(&flat_map);
+
+        let mut builder = ChmBuilder::new("Book", ChmLanguage::default(), "book.chm");
+        let mut settings = KeywordSettings {
+            strategy: std::sync::Arc::new(crate::chm::keyworder::Off),
+            headings: crate::chm::keyworder::HeadingEntries {
+                max_level: 3,
+                nested: false,
+                exclude: ["summary".to_string(), "src\\c.html#notes".to_string()].into(),
+            },
+            ..KeywordSettings::default()
+        };
+        builder.with_keyword_settings(settings.clone());
+        let index = builder
+            .build_index(&flat_map, std::slice::from_ref(&file), &titles)
+            .to_string();
+        assert!(index.contains(
+            r#"<param name="Name" value="Vec&lt;T&gt;">        <param name="Local" value="src\c.html#vec">"#
+        ));
+        assert!(index.contains(r#"value="src\c.html#capacity""#));
+        for missing in ["#collections", "#details", "#summary", "#notes"] {
+            assert!(!index.contains(missing), "{missing} in {index}");
+        }
+
+        settings.headings.nested = true;
+        builder.with_keyword_settings(settings);
+        let index = builder.build_index(&flat_map, &[file], &titles).to_string();
+        assert!(index.contains(concat!(
+            r#"<param name="Name" value="Collections">        <param name="Local" value="src\c.html">        </OBJECT>"#,
+            "\n    <UL>\n",
+            r#"        <LI> <OBJECT type="text/sitemap">        <param name="Name" value="Vec&lt;T&gt;">"#
+        )));
+    }
+
+    #[test]
+    fn index_escapes_titles() {
+        let builder = ChmBuilder::new("Book", ChmLanguage::default(), "book.chm");
+        let (entry, file) = topic("Vec<T> & Friends", "b.html", "<p>Text</p>");
+        let flat_map = [entry];
+        let titles = topic_titles(&flat_map);
+
+        let index = builder.build_index(&flat_map, &[file], &titles).to_string();
+        assert!(index.contains(r#"value="Vec&lt;T&gt; &amp; Friends""#));
+        assert!(!index.contains("Vec<T>"));
+    }
+}
@@ -0,0 +1,150 @@
+//! Associative links (`ALinks`) group topics that are related to each other.
+//! Topics declare the groups they belong to, and each gets a "Related topics" control listing the other members of its groups
+use crate::chm::utilities::escape_html;
+use regex::Regex;
+use std::{fmt::Write, sync::LazyLock};
+
+/// Class ID of the object holding the `ALink` names of a topic
+const SITEMAP_CLASSID: &str = "clsid:1e2a7bd0-dab9-11d0-b93a-00c04fc99f9e";
+
+/// Class ID of the HTML Help `ActiveX` control
+const HHCTRL_CLASSID: &str = "clsid:adb880a6-d8ff-11cf-9377-00aa003b7a11";
+
+static PLACEHOLDER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<!--chm-related(?::(.*?))?-->").unwrap());
+
+/// Placeholder left in a topic where its related topics go.\
+/// Without groups, the groups of the topic itself are used
+#[must_use]
+pub fn related_placeholder(groups: &[String]) -> String {
+    if groups.is_empty() {
+        "<!--chm-related-->".to_string()
+    } else {
+        let groups = groups
+            .iter()
+            .map(|group| escape_html(group))
+            .collect::<Vec<_>>();
+        format!("<!--chm-related:{}-->", groups.join(";"))
+    }
+}
+
+/// The object naming the `ALink` groups of a topic, which the compiler turns into the associative index
+#[must_use]
+pub fn alink_names(groups: &[String]) -> String {
+    let mut object =
+        format!(r#"<OBJECT type="application/x-oleobject" classid="{SITEMAP_CLASSID}">"#);
+    for group in groups {
+        write!(
+            object,
+            r#"<param name="ALink Name" value="{}">"#,
+            escape_html(group)
+        )
+        .ok();
+    }
+    object.push_str("</OBJECT>");
+    object
+}
+
+/// How the related topics of a topic are shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RelatedTopicsStyle {
+    /// A static list of links, which works in any viewer
+    #[default]
+    List,
+
+    /// A viewer button opening a menu of the related topics
+    Button,
+
+    /// Not shown. The `ALink` names are still compiled, for other files to use
+    None,
+}
+
+/// Appearance of the "Related topics" control placed in topics
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RelatedTopics {
+    /// How the related topics are shown
+    pub style: RelatedTopicsStyle,
+
+    /// Heading of the list, or text of the button
+    pub title: String,
+}
+impl Default for RelatedTopics {
+    fn default() -> Self {
+        Self {
+            style: RelatedTopicsStyle::default(),
+            title: "Related topics".to_string(),
+        }
+    }
+}
+impl RelatedTopics {
+    /// Replaces the related-topic placeholders in a topic belonging to `own_groups`.\
+    /// `related` is called with the escaped groups of each placeholder, and returns the `(url, title)` of the matching topics
+    pub fn fill<'t>(
+        &self,
+        html: &'t str,
+        own_groups: &[String],
+        mut related: impl FnMut(&[String]) -> Vec<(String, String)>,
+    ) -> std::borrow::Cow<'t, str> {
+        PLACEHOLDER_REGEX.replace_all(html, |captures: &regex::Captures<'_>| {
+            let groups = match captures.get(1) {
+                Some(groups) => groups.as_str().split(';').map(str::to_string).collect(),
+                None => own_groups
+                    .iter()
+                    .map(|group| escape_html(group))
+                    .collect::<Vec<_>>(),
+            };
+            match self.style {
+                RelatedTopicsStyle::List => self.list(&related(&groups)),
+                RelatedTopicsStyle::Button => self.button(&related(&groups)),
+                RelatedTopicsStyle::None => String::new(),
+            }
+        })
+    }
+
+    /// A static list of links to the related topics, or nothing if there are none
+    fn list(&self, topics: &[(String, String)]) -> String {
+        if topics.is_empty() {
+            return String::new();
+        }
+
+        let mut list = format!(
+            r#"<div class="related-topics"><h4>{}</h4><ul>"#,
+            escape_html(&self.title)
+        );
+        for (url, title) in topics {
+            write!(list, r#"<li><a href="{url}">{title}</a></li>"#).ok();
+        }
+        list.push_str("</ul></div>");
+        list
+    }
+
+    /// A button showing the related topics in a menu, or nothing if there are none.\
+    /// The topics are listed rather than looked up by `ALink` name, which would also list the topic itself
+    fn button(&self, topics: &[(String, String)]) -> String {
+        if topics.is_empty() {
+            return String::new();
+        }
+
+        let mut button = format!(
+            concat!(
+                r#"<OBJECT type="application/x-oleobject" classid="{}">"#,
+                r#"<param name="Command" value="Related Topics,MENU">"#,
+                r#"<param name="Button" value="Text:{}">"#,
+            ),
+            HHCTRL_CLASSID,
+            escape_html(&self.title),
+        );
+        for (number, (url, title)) in topics.iter().enumerate() {
+            write!(
+                button,
+                r#"<param name="Item{}" value="{title};{url}">"#,
+                number + 1
+            )
+            .ok();
+        }
+        button.push_str("</OBJECT>");
+        button
+    }
+}
@@ -0,0 +1,132 @@
+//! Checks that links pointing at an anchor, like `ch02.md#error-handling`, land on an anchor that exists
+use super::contents::File;
+use crate::chm::utilities::normalize_path;
+use std::collections::{HashMap, HashSet};
+
+/// What to do about links pointing at anchors missing from their topic
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeadAnchors {
+    /// Do not check anchors
+    Off,
+
+    /// Print a warning for each dead anchor
+    #[default]
+    Warn,
+
+    /// Fail the build if there are any dead anchors
+    Error,
+}
+impl DeadAnchors {
+    /// Checks the anchor links in the files against the anchors of their target topics.\
+    /// Links to topics that are not in the files are not checked
+    ///
+    /// # Errors
+    /// Will return an error listing the dead anchors, if set to [`DeadAnchors::Error`]
+    pub fn check(self, files: &[File]) -> std::io::Result<()> {
+        if self == Self::Off {
+            return Ok(());
+        }
+
+        let dead = dead_anchors(files);
+        if dead.is_empty() {
+            return Ok(());
+        }
+        if self == Self::Error {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Found links to missing anchors:\n{}", dead.join("\n")),
+            ));
+        }
+
+        for message in dead {
+            eprintln!("Warning: {message}");
+        }
+        Ok(())
+    }
+}
+
+/// Describes each link in the files pointing at an anchor missing from its target topic
+fn dead_anchors(files: &[File]) -> Vec<String> {
+    let anchors: HashMap<String, &HashSet<String>> = files
+        .iter()
+        .filter_map(|file| Some((normalize_path(&file.path)?, &file.metadata.anchors)))
+        .collect();
+
+    let mut dead = vec![];
+    for file in files {
+        for link in &file.metadata.anchor_links {
+            if let Some(target) = anchors.get(&link.target)
+                && !target.contains(&link.anchor)
+            {
+                dead.push(format!(
+                    "`{}` links to `{}`, but `{}` has no anchor named `{}`",
+                    file.path.display(),
+                    link.url,
+                    link.target,
+                    link.anchor
+                ));
+            }
+        }
+    }
+    dead
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chm::inputs::{InputOptions, md_load};
+    use std::{path::Path, sync::Arc};
+
+    /// Two chapters, the first linking to a live and a dead anchor of the second
+    fn chapters(links: &str) -> Vec<File> {
+        let chapters = ["src/a.md", "src/b.md"].map(ToString::to_string);
+        let options = InputOptions {
+            chapters: Some(Arc::new(HashSet::from(chapters))),
+            ..InputOptions::default()
+        };
+        [
+            ("src/a.md", format!("# A\n\n{links}\n")),
+            (
+                "src/b.md",
+                "# B\n\n## Usage\n\n<a name=\"raw\"></a>\n".to_string(),
+            ),
+        ]
+        .into_iter()
+        .map(|(path, markdown)| {
+            md_load(Path::new(path), markdown.as_bytes(), &options)
+                .unwrap()
+                .0
+        })
+        .collect()
+    }
+
+    #[test]
+    fn live_anchors_pass() {
+        let files = chapters("[usage](b.md#usage) [raw](b.md#raw) [top](#a) [page](b.md)");
+        assert!(dead_anchors(&files).is_empty());
+        assert!(DeadAnchors::Error.check(&files).is_ok());
+    }
+
+    #[test]
+    fn dead_anchors_are_reported() {
+        let files = chapters("[usage](b.md#usage) [gone](b.md#missing) [here](#nowhere)");
+        assert_eq!(
+            dead_anchors(&files),
+            [
+                "`src/a.html` links to `b.md#missing`, but `src/b.html` has no anchor named `missing`",
+                "`src/a.html` links to `#nowhere`, but `src/a.html` has no anchor named `nowhere`",
+            ]
+        );
+
+        assert!(DeadAnchors::Warn.check(&files).is_ok());
+        assert!(DeadAnchors::Off.check(&files).is_ok());
+        let error = DeadAnchors::Error.check(&files).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(
+            error.to_string().starts_with(
+                "Found links to missing anchors:\n`src/a.html` links to `b.md#missing`"
+            )
+        );
+    }
+}
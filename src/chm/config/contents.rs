@@ -1,8 +1,10 @@
 //! Module for managing dependencies
 //!
 //! Reads and stores files, converts docs to HTML
-use crate::chm::inputs::md_load;
+use super::popups::ChmTextPopup;
+use crate::chm::inputs::{InputOptions, css_load, md_load};
 use std::{
+    collections::HashSet,
     ffi::OsStr,
     path::{Path, PathBuf},
 };
@@ -10,9 +12,15 @@ use std::{
 /// All the files included in the CHM file.
 #[derive(Debug, Clone)]
 pub struct IncludedFiles {
-    /// The list of files found.  
+    /// The list of files found.\
     /// The last one is the original file included
     pub files: Vec<File>,
+
+    /// Options used when rendering the files
+    pub options: InputOptions,
+
+    /// Dependencies already added, so cycles between them end
+    seen: HashSet<PathBuf>,
 }
 impl Default for IncludedFiles {
     fn default() -> Self {
@@ -23,7 +31,17 @@ impl IncludedFiles {
     /// Creates a new `IncludedFiles` instance with the given source root and optional body regex.
     #[must_use]
     pub fn new() -> Self {
-        Self { files: vec![] }
+        Self::with_options(InputOptions::default())
+    }
+
+    /// Creates a new `IncludedFiles` instance which renders files with the given options
+    #[must_use]
+    pub fn with_options(options: InputOptions) -> Self {
+        Self {
+            files: vec![],
+            options,
+            seen: HashSet::new(),
+        }
     }
 
     /// Adds a file to the list of included files, and processes it if it is an HTML file.
@@ -35,22 +53,55 @@ impl IncludedFiles {
         let src_path = path.as_ref();
         println!("Processing `{}`", src_path.display());
 
-        let (file, dependencies) = match src_path.extension().and_then(OsStr::to_str) {
-            Some("md") => md_load(src_path, contents)?,
+        let (mut file, dependencies) = match src_path.extension().and_then(OsStr::to_str) {
+            Some("md") => md_load(src_path, contents, &self.options)?,
+            Some("css") => css_load(src_path, contents, &self.options)?,
             _ => {
                 let file = File {
                     path: src_path.to_path_buf(),
-                    contents: std::fs::read(path)?,
+                    contents: contents.to_vec(),
+                    metadata: FileMetadata::default(),
                 };
                 (file, vec![])
             }
         };
 
         for dependency in dependencies {
-            let contents = std::fs::read(&dependency)?;
+            //
+            // Stylesheets can import each other
+            if !self.seen.insert(dependency.clone()) {
+                continue;
+            }
+            let source = match &self.options.confinement {
+                Some(confinement) => confinement.source(&dependency).map_err(|e| {
+                    std::io::Error::new(
+                        e.kind(),
+                        format!("{e}, referenced from `{}`", src_path.display()),
+                    )
+                })?,
+                None => dependency.clone(),
+            };
+            let contents = std::fs::read(&source).map_err(|e| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!(
+                        "Could not read `{}`, referenced from `{}`: {e}",
+                        dependency.display(),
+                        src_path.display()
+                    ),
+                )
+            })?;
             self.add_file(dependency, &contents)?;
         }
 
+        //
+        // Generated files are named after their content, so topics can share them
+        for generated in std::mem::take(&mut file.metadata.generated) {
+            if self.seen.insert(generated.path.clone()) {
+                self.files.push(generated);
+            }
+        }
+
         self.files.push(file);
         Ok(())
     }
@@ -69,6 +120,9 @@ pub struct File {
 
     /// File contents
     pub contents: Vec<u8>,
+
+    /// Information extracted from the file while loading it
+    pub metadata: FileMetadata,
 }
 impl File {
     /// Returns true if this file is an HTML document
@@ -86,3 +140,83 @@ impl File {
         }
     }
 }
+
+/// Information extracted from a file while loading it, which belongs to the project instead of the page
+#[derive(Debug, Clone, Default)]
+pub struct FileMetadata {
+    /// Text popups declared in the file
+    pub popups: Vec<ChmTextPopup>,
+
+    /// Index terms marked by the author
+    pub index_markers: Vec<IndexMarker>,
+
+    /// Headings in the file, in order
+    pub headings: Vec<Heading>,
+
+    /// Rust identifiers found in the code of the file, in order
+    pub code_identifiers: Vec<CodeIdentifier>,
+
+    /// `ALink` groups the file belongs to
+    pub alinks: Vec<String>,
+
+    /// Names of the anchors in the file, which links can point at
+    pub anchors: HashSet<String>,
+
+    /// Links pointing at an anchor, in this file or another topic
+    pub anchor_links: Vec<AnchorLink>,
+
+    /// Files made while loading the file, like images of its math
+    pub generated: Vec<File>,
+}
+
+/// A link pointing at an anchor in a topic, like `ch02.md#error-handling`
+#[derive(Debug, Clone)]
+pub struct AnchorLink {
+    /// The link as written
+    pub url: String,
+
+    /// Path of the target topic, normalized with [`crate::chm::utilities::normalize_path`]
+    pub target: String,
+
+    /// Name of the anchor
+    pub anchor: String,
+}
+
+/// A Rust identifier found in inline code or a code block, like `HashMap::entry`, `println!` or `#[derive]`
+#[derive(Debug, Clone)]
+pub struct CodeIdentifier {
+    /// The identifier
+    pub name: String,
+
+    /// Name of the anchor in front of the code
+    pub anchor: String,
+
+    /// True if the code defines the identifier, instead of just mentioning it
+    pub defined: bool,
+}
+
+/// A heading in a file
+#[derive(Debug, Clone)]
+pub struct Heading {
+    /// Level of the heading, 1 for `#`
+    pub level: u8,
+
+    /// ID of the anchor at the start of the heading
+    pub id: String,
+
+    /// Text of the heading
+    pub title: String,
+}
+
+/// An index term marked by the author, pointing at an anchor in the file
+#[derive(Debug, Clone)]
+pub struct IndexMarker {
+    /// Levels of the term, like `["Vec", "push"]`
+    pub terms: Vec<String>,
+
+    /// Name of the anchor the term points at
+    pub anchor: String,
+
+    /// Another term this one redirects to, instead of the anchor
+    pub see_also: Option<String>,
+}
@@ -0,0 +1,189 @@
+//! A curated index holds reviewed index terms, their synonyms, and terms that must never be indexed.
+//! It is merged with the generated index when the project is written.
+use std::collections::BTreeMap;
+
+/// Reviewed index terms and exclusions
+#[derive(Debug, Clone, Default)]
+pub struct CuratedIndex {
+    /// Terms to add to the index
+    pub keywords: Vec<CuratedKeyword>,
+
+    /// Terms removed from the index, wherever they came from
+    pub blocklist: Vec<String>,
+}
+impl CuratedIndex {
+    /// Parse a curated index file in the following format:
+    /// ```toml
+    /// blocklist = ["foo", "bar"]
+    ///
+    /// [keywords]
+    /// "Borrowing" = "ch02.md"
+    /// "Vec > push" = ["ch03.md#push", "ch05.md"]
+    /// "Ownership" = { targets = ["ch01.md#ownership"], synonyms = ["Owned values"] }
+    /// "Lifetimes" = { see-also = "Borrowing" }
+    /// ```
+    /// Use `>` to nest a term under another.
+    /// Targets are chapter paths relative to the book source, with an optional anchor
+    ///
+    /// # Errors
+    /// Will return an error if the file is not valid TOML, or an entry is malformed
+    pub fn from_toml(source: &str) -> std::io::Result<Self> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Entry {
+            Target(String),
+            Targets(Vec<String>),
+            Full(FullEntry),
+        }
+
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "kebab-case", deny_unknown_fields)]
+        struct FullEntry {
+            #[serde(default)]
+            targets: Vec<String>,
+            #[serde(default)]
+            synonyms: Vec<String>,
+            see_also: Option<String>,
+        }
+
+        #[derive(serde::Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct File {
+            #[serde(default)]
+            blocklist: Vec<String>,
+            #[serde(default)]
+            keywords: BTreeMap<String, Entry>,
+        }
+
+        let file: File = toml::from_str(source)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+
+        let mut keywords = vec![];
+        for (term, entry) in file.keywords {
+            let entry = match entry {
+                Entry::Target(target) => FullEntry {
+                    targets: vec![target],
+                    synonyms: vec![],
+                    see_also: None,
+                },
+                Entry::Targets(targets) => FullEntry {
+                    targets,
+                    synonyms: vec![],
+                    see_also: None,
+                },
+                Entry::Full(entry) => entry,
+            };
+
+            if entry.targets.is_empty() && entry.see_also.is_none() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Index term `{term}` needs at least one target, or a see-also"),
+                ));
+            }
+
+            keywords.push(CuratedKeyword {
+                terms: split_levels(&term)?,
+                synonyms: entry
+                    .synonyms
+                    .iter()
+                    .map(|synonym| split_levels(synonym))
+                    .collect::<std::io::Result<_>>()?,
+                targets: entry.targets,
+                see_also: entry.see_also,
+            });
+        }
+
+        Ok(Self {
+            keywords,
+            blocklist: file.blocklist,
+        })
+    }
+}
+
+/// A reviewed index term
+#[derive(Debug, Clone)]
+pub struct CuratedKeyword {
+    /// Levels of the term, like `["Vec", "push"]`
+    pub terms: Vec<String>,
+
+    /// Other terms pointing at the same targets
+    pub synonyms: Vec<Vec<String>>,
+
+    /// Chapters the term points at, relative to the book source, with an optional anchor
+    pub targets: Vec<String>,
+
+    /// Another term this one redirects to
+    pub see_also: Option<String>,
+}
+
+/// Split a term like `Vec > push` into its levels
+fn split_levels(term: &str) -> std::io::Result<Vec<String>> {
+    let levels = term
+        .split('>')
+        .map(|level| level.trim().to_string())
+        .collect::<Vec<_>>();
+    if levels.iter().any(String::is_empty) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Invalid index term `{term}`"),
+        ));
+    }
+    Ok(levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_terms_synonyms_and_blocklist() {
+        let curated = CuratedIndex::from_toml(
+            r#"
+            blocklist = ["foo"]
+
+            [keywords]
+            "Borrowing" = "ch02.md"
+            "Vec > push" = ["ch03.md#push", "ch05.md"]
+            "Ownership" = { targets = ["ch01.md#ownership"], synonyms = ["Owned values", "Values > owned"] }
+            "Lifetimes" = { see-also = "Borrowing" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(curated.blocklist, ["foo"]);
+
+        let keyword = |term: &str| {
+            curated
+                .keywords
+                .iter()
+                .find(|keyword| keyword.terms.join(" > ") == term)
+                .unwrap()
+        };
+        assert_eq!(keyword("Borrowing").targets, ["ch02.md"]);
+        assert_eq!(keyword("Vec > push").targets, ["ch03.md#push", "ch05.md"]);
+        assert_eq!(
+            keyword("Ownership").synonyms,
+            [
+                vec!["Owned values".to_string()],
+                vec!["Values".to_string(), "owned".to_string()]
+            ]
+        );
+        assert_eq!(keyword("Lifetimes").see_also.as_deref(), Some("Borrowing"));
+    }
+
+    #[test]
+    fn rejects_malformed_terms() {
+        let error = |source: &str| CuratedIndex::from_toml(source).unwrap_err().to_string();
+        assert!(
+            error("[keywords]\n\"Vec >\" = \"ch03.md\"").contains("Invalid index term `Vec >`")
+        );
+        assert!(
+            error("[keywords]\nVec = { synonyms = [\"Vectors\"] }")
+                .contains("needs at least one target")
+        );
+        assert!(
+            error("[keywords]\nVec = { target = \"ch03.md\" }")
+                .contains("did not match any variant")
+        );
+        assert!(error("blocklst = []").contains("unknown field `blocklst`"));
+    }
+}
@@ -1,12 +1,24 @@
 //! The help table of contents (.hhc) file is an HTML file that contains the topic titles for your table of contents.
 //! When a user opens the table of contents in a compiled help file (or on a Web page) and clicks a topic title, the HTML file associated with that title will open.
-use super::contents::{File, IncludedFiles};
-use crate::chm::utilities::MakeAbsolute;
+use super::{
+    contents::{File, IncludedFiles},
+    hhp::ChmFont,
+};
+use crate::chm::{
+    inputs::InputOptions,
+    utilities::{MakeAbsolute, escape_html},
+};
 use std::path::Path;
 
 /// The TOC for the CHM file.
-#[derive(Debug, Clone)]
-pub struct ChmContents(pub Vec<ChmContentsEntry>);
+#[derive(Debug, Clone, Default)]
+pub struct ChmContents {
+    /// Appearance of the TOC pane
+    pub properties: ChmContentsProperties,
+
+    /// Top-level entries
+    pub entries: Vec<ChmContentsEntry>,
+}
 impl ChmContents {
     const HEADER: &'static str = concat!(
         r#"<!DOCTYPE HTML PUBLIC "-//IETF//DTD HTML//EN">\n"#,
@@ -15,11 +27,6 @@ impl ChmContents {
         r#"<meta name="GENERATOR" content="@rscarson&reg; mdbook-chm">\n"#,
         r#"<!-- Sitemap 1.0 -->\n"#,
         r#"</HEAD><BODY>\n"#,
-        r#"<OBJECT type="text/site properties">\n"#,
-        r#"    <param name="Type" value=" ">\n"#,
-        r#"    <param name="TypeDesc" value=" ">\n"#,
-        r#"    <param name="Window Styles" value="0x800025">\n"#,
-        r#"</OBJECT>\n"#,
     );
 
     /// Flatten this object into a list of entries instead of a tree
@@ -28,7 +35,7 @@ impl ChmContents {
     #[must_use]
     pub fn flatten(mut self) -> Vec<ChmContentsEntry> {
         let mut result = vec![];
-        for entry in self.0.drain(..) {
+        for entry in self.entries.drain(..) {
             result.extend(entry.flatten());
         }
         result
@@ -36,23 +43,187 @@ impl ChmContents {
 }
 impl std::fmt::Display for ChmContents {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let children = self.0.iter().map(|e| e.format(1)).collect::<Vec<_>>();
+        let children = self.entries.iter().map(|e| e.format(1)).collect::<Vec<_>>();
 
         write!(
             f,
             concat!(
                 "{header}\n",
+                "{properties}\n",
                 "<UL>\n",
                 "{body}\n",
                 "</UL>\n",
                 "</BODY></HTML>"
             ),
             header = Self::HEADER,
+            properties = self.properties,
             body = children.join("\n")
         )
     }
 }
 
+/// Site properties controlling how the TOC pane looks and behaves
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ChmContentsProperties {
+    /// Show plus/minus buttons next to folders
+    pub plus_minus: bool,
+
+    /// Draw lines between entries
+    pub lines: bool,
+
+    /// Draw lines and buttons for the top-level entries too
+    pub lines_at_root: bool,
+
+    /// Keep the selection highlighted when the pane loses focus
+    pub show_selection: bool,
+
+    /// Underline entries under the mouse
+    pub track_select: bool,
+
+    /// Expand a folder with a single click, collapsing the others
+    pub single_expand: bool,
+
+    /// Highlight the whole row of the selected entry
+    pub full_row_select: bool,
+
+    /// Draw a border around the pane
+    pub border: bool,
+
+    /// Raw `Window Styles` value, overriding all of the flags above
+    pub window_styles: Option<u32>,
+
+    /// Raw `ExWindow Styles` value
+    pub ex_window_styles: Option<u32>,
+
+    /// The font used in the TOC pane
+    pub font: Option<ChmFont>,
+
+    /// Use folder icons instead of books for entries with children
+    pub folder_icons: bool,
+
+    /// Path to a custom bitmap of icons, selected per entry with `image-number`
+    pub image_list: Option<String>,
+
+    /// Width of a single icon in the custom bitmap
+    pub image_width: Option<u32>,
+}
+impl ChmContentsProperties {
+    const TVS_HASBUTTONS: u32 = 0x1;
+    const TVS_HASLINES: u32 = 0x2;
+    const TVS_LINESATROOT: u32 = 0x4;
+    const TVS_SHOWSELALWAYS: u32 = 0x20;
+    const TVS_TRACKSELECT: u32 = 0x200;
+    const TVS_SINGLEEXPAND: u32 = 0x400;
+    const TVS_FULLROWSELECT: u32 = 0x1000;
+    const WS_BORDER: u32 = 0x0080_0000;
+
+    /// Returns the `Window Styles` value for the pane
+    #[must_use]
+    pub fn window_styles(&self) -> u32 {
+        if let Some(styles) = self.window_styles {
+            return styles;
+        }
+
+        [
+            (self.plus_minus, Self::TVS_HASBUTTONS),
+            (self.lines, Self::TVS_HASLINES),
+            (self.lines_at_root, Self::TVS_LINESATROOT),
+            (self.show_selection, Self::TVS_SHOWSELALWAYS),
+            (self.track_select, Self::TVS_TRACKSELECT),
+            (self.single_expand, Self::TVS_SINGLEEXPAND),
+            (self.full_row_select, Self::TVS_FULLROWSELECT),
+            (self.border, Self::WS_BORDER),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .fold(0, |styles, (_, flag)| styles | flag)
+    }
+}
+impl Default for ChmContentsProperties {
+    fn default() -> Self {
+        Self {
+            plus_minus: true,
+            lines: false,
+            lines_at_root: true,
+            show_selection: true,
+            track_select: false,
+            single_expand: false,
+            full_row_select: false,
+            border: true,
+            window_styles: None,
+            ex_window_styles: None,
+            font: None,
+            folder_icons: false,
+            image_list: None,
+            image_width: None,
+        }
+    }
+}
+impl std::fmt::Display for ChmContentsProperties {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut params = vec![
+            ("Type".to_string(), " ".to_string()),
+            ("TypeDesc".to_string(), " ".to_string()),
+            (
+                "Window Styles".to_string(),
+                format!("{:#x}", self.window_styles()),
+            ),
+        ];
+        if let Some(styles) = self.ex_window_styles {
+            params.push(("ExWindow Styles".to_string(), format!("{styles:#x}")));
+        }
+        if let Some(font) = &self.font {
+            params.push(("Font".to_string(), font.to_string()));
+        }
+        if self.folder_icons {
+            params.push(("ImageType".to_string(), "Folder".to_string()));
+        }
+        if let Some(image_list) = &self.image_list {
+            let name = Path::new(image_list)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            params.push(("ImageList".to_string(), escape_html(&name)));
+        }
+        if let Some(width) = self.image_width {
+            params.push(("Image Width".to_string(), width.to_string()));
+        }
+
+        writeln!(f, r#"<OBJECT type="text/site properties">"#)?;
+        for (name, value) in params {
+            writeln!(f, r#"    <param name="{name}" value="{value}">"#)?;
+        }
+        write!(f, "</OBJECT>")
+    }
+}
+
+/// Per-entry appearance settings for the table of contents
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ChmEntryProperties {
+    /// Index of the icon to use, in the built-in or custom image list
+    pub image_number: Option<u32>,
+
+    /// Mark the entry with the `new` icon overlay
+    pub new: Option<bool>,
+
+    /// Comment attached to the entry
+    pub comment: Option<String>,
+}
+impl ChmEntryProperties {
+    /// Returns these properties, with any field set in `overrides` replaced
+    #[must_use]
+    pub fn merged(&self, overrides: &Self) -> Self {
+        Self {
+            image_number: overrides.image_number.or(self.image_number),
+            new: overrides.new.or(self.new),
+            comment: overrides.comment.clone().or_else(|| self.comment.clone()),
+        }
+    }
+}
+
 /// A directory tree structure for the table of contents.
 #[derive(Debug, Clone)]
 pub struct ChmContentsEntry {
@@ -67,6 +238,12 @@ pub struct ChmContentsEntry {
 
     /// All included files for this chapter (not the children)
     pub files: Vec<File>,
+
+    /// Appearance of this entry in the TOC
+    pub properties: ChmEntryProperties,
+
+    /// A blank line between other entries, without a title, page or children
+    pub separator: bool,
 }
 impl ChmContentsEntry {
     /// Create a new entry based on a source file, and process dependencies
@@ -89,7 +266,23 @@ impl ChmContentsEntry {
         source: impl AsRef<Path>,
         contents: &str,
     ) -> std::io::Result<Self> {
-        let mut files = IncludedFiles::new();
+        Self::with_options(title, source, contents, InputOptions::default())
+    }
+
+    /// Create a new entry based on a source file and contents, and process dependencies using the given options
+    ///
+    /// # Errors
+    /// Can return an error on IO failures
+    ///
+    /// # Panics
+    /// dont worry 'bout it kay?
+    pub fn with_options(
+        title: &impl ToString,
+        source: impl AsRef<Path>,
+        contents: &str,
+        options: InputOptions,
+    ) -> std::io::Result<Self> {
+        let mut files = IncludedFiles::with_options(options);
         files.add_file(source, contents.as_bytes())?;
 
         let own_path = &files.files.last().expect("We literally just added it").path;
@@ -99,18 +292,85 @@ impl ChmContentsEntry {
 
             children: vec![],
             files: files.files.into_iter().collect(),
+            properties: ChmEntryProperties::default(),
+            separator: false,
         })
     }
 
+    /// Create an entry without a page of its own, used to group other entries
+    #[must_use]
+    pub fn folder(title: &impl ToString) -> Self {
+        Self {
+            title: title.to_string(),
+            file: String::new(),
+            children: vec![],
+            files: vec![],
+            properties: ChmEntryProperties::default(),
+            separator: false,
+        }
+    }
+
+    /// Create a blank entry, used to add spacing between other entries
+    #[must_use]
+    pub fn separator() -> Self {
+        Self {
+            separator: true,
+            ..Self::folder(&"")
+        }
+    }
+
+    /// Returns the page shown when this entry is opened, or the first page below it for folders
+    #[must_use]
+    pub fn first_page(&self) -> Option<&File> {
+        self.files
+            .last()
+            .or_else(|| self.children.iter().find_map(Self::first_page))
+    }
+
     /// Format the entry as a string.
     pub(crate) fn format(&self, depth: usize) -> String {
         let tabs = "\t".repeat(depth);
+        if self.separator {
+            //
+            // An empty name would be shown as untitled
+            return format!(
+                "{tabs}<LI><OBJECT type=\"text/sitemap\">\n\
+                 {tabs}\t<param name=\"Name\" value=\"&nbsp;\">\n\
+                 {tabs}</OBJECT>"
+            );
+        }
+
         let mut result = vec![
             format!("{tabs}<LI><OBJECT type=\"text/sitemap\">"),
-            format!("{tabs}\t<param name=\"Name\" value=\"{}\">", self.title),
-            format!("{tabs}\t<param name=\"Local\" value=\"{}\">", self.file),
-            format!("{tabs}</OBJECT>"),
+            format!(
+                "{tabs}\t<param name=\"Name\" value=\"{}\">",
+                escape_html(&self.title)
+            ),
         ];
+        if !self.file.is_empty() {
+            result.push(format!(
+                "{tabs}\t<param name=\"Local\" value=\"{}\">",
+                self.file
+            ));
+        }
+        if let Some(image_number) = self.properties.image_number {
+            result.push(format!(
+                "{tabs}\t<param name=\"ImageNumber\" value=\"{image_number}\">"
+            ));
+        }
+        if let Some(new) = self.properties.new {
+            result.push(format!(
+                "{tabs}\t<param name=\"New\" value=\"{}\">",
+                u8::from(new)
+            ));
+        }
+        if let Some(comment) = &self.properties.comment {
+            result.push(format!(
+                "{tabs}\t<param name=\"Comment\" value=\"{}\">",
+                escape_html(comment)
+            ));
+        }
+        result.push(format!("{tabs}</OBJECT>"));
 
         if !self.children.is_empty() {
             result.push(format!("{tabs}<UL>"));
@@ -137,3 +397,25 @@ impl ChmContentsEntry {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn part_titles_are_escaped() {
+        let mut part = ChmContentsEntry::folder(&r#"C# & <Rust> "Guide""#);
+        part.children.push(ChmContentsEntry::separator());
+        let contents = ChmContents {
+            entries: vec![part],
+            ..ChmContents::default()
+        }
+        .to_string();
+
+        assert!(
+            contents
+                .contains(r#"<param name="Name" value="C# &amp; &lt;Rust&gt; &quot;Guide&quot;">"#)
+        );
+        assert!(!contents.contains("<Rust>"));
+    }
+}
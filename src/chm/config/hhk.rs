@@ -1,9 +1,15 @@
 //! The index (.hhk) file is an HTML file that contains the index entries (keywords) for your index.
 //! When a user opens the index in a compiled help file, or on a Web page, and clicks a keyword, the HTML file associated with the keyword will open.
+use std::collections::{HashMap, HashSet};
 
 /// The index for the CHM project
-#[derive(Debug, Clone)]
-pub struct ChmIndex(pub Vec<ChmIndexEntry>);
+///
+/// Entries sharing a keyword are merged, so the viewer shows a "Topics Found" chooser instead of duplicate rows
+#[derive(Debug, Clone, Default)]
+pub struct ChmIndex {
+    entries: Vec<ChmIndexEntry>,
+    positions: HashMap<String, usize>,
+}
 impl ChmIndex {
     const HEADER: &'static str = concat!(
         r#"<!DOCTYPE HTML PUBLIC "-//IETF//DTD HTML//EN">"#,
@@ -13,50 +19,323 @@ impl ChmIndex {
         r#"<!-- Sitemap 1.0 -->"#,
         r#"</HEAD><BODY>"#,
     );
+
+    /// Create an empty index
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry to the index, merging it into any existing entry with the same keyword.\
+    /// Keywords are compared ignoring case, like the viewer does
+    pub fn insert(&mut self, entry: ChmIndexEntry) {
+        let key = entry.keyword.to_lowercase();
+        if let Some(&position) = self.positions.get(&key) {
+            self.entries[position].merge(entry);
+        } else {
+            self.positions.insert(key, self.entries.len());
+            self.entries.push(entry);
+        }
+    }
+
+    /// Removes every entry with one of the given keywords, at any level.\
+    /// The keywords must be lowercase
+    pub fn remove_keywords(&mut self, blocked: &HashSet<String>) {
+        self.entries = std::mem::take(&mut self.entries)
+            .into_iter()
+            .filter_map(|entry| entry.without_keywords(blocked))
+            .collect();
+
+        self.positions.clear();
+        for (position, entry) in self.entries.iter().enumerate() {
+            self.positions
+                .insert(entry.keyword.to_lowercase(), position);
+        }
+    }
+
+    /// The top-level entries of the index
+    #[must_use]
+    pub fn entries(&self) -> &[ChmIndexEntry] {
+        &self.entries
+    }
+}
+impl Extend<ChmIndexEntry> for ChmIndex {
+    fn extend<T: IntoIterator<Item = ChmIndexEntry>>(&mut self, iter: T) {
+        for entry in iter {
+            self.insert(entry);
+        }
+    }
 }
 impl std::fmt::Display for ChmIndex {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            concat!(
-                "{header}\n",
-                "<UL>\n",
-                "{body}\n",
-                "</UL>\n",
-                "</BODY></HTML>"
-            ),
-            header = Self::HEADER,
-            body = self
-                .0
-                .iter()
-                .map(ToString::to_string)
-                .collect::<Vec<_>>()
-                .join("\n")
-        )
+        writeln!(f, "{header}\n<UL>", header = Self::HEADER)?;
+        for entry in &self.entries {
+            writeln!(f, "{entry}")?;
+        }
+        write!(f, "</UL>\n</BODY></HTML>")
     }
 }
 
 /// A single entry in the index
+///
+/// Values are written to the index as-is, and should already be HTML-escaped
 #[derive(Debug, Clone)]
 pub struct ChmIndexEntry {
     /// Keyword to display
     pub keyword: String,
 
-    /// Path for the keyword
-    pub file: String,
+    /// Topics the keyword points at
+    pub topics: Vec<ChmIndexTopic>,
+
+    /// Another keyword this one redirects to
+    pub see_also: Option<String>,
+
+    /// Sub-entries, displayed indented under this one
+    pub children: Vec<ChmIndexEntry>,
+}
+impl ChmIndexEntry {
+    /// Create an entry pointing at a single topic
+    #[must_use]
+    pub fn new(keyword: &impl ToString, topic: ChmIndexTopic) -> Self {
+        Self {
+            keyword: keyword.to_string(),
+            topics: vec![topic],
+            see_also: None,
+            children: vec![],
+        }
+    }
+
+    /// Create an entry that redirects to another keyword
+    #[must_use]
+    pub fn see_also(keyword: &impl ToString, target: &impl ToString) -> Self {
+        Self {
+            keyword: keyword.to_string(),
+            topics: vec![],
+            see_also: Some(target.to_string()),
+            children: vec![],
+        }
+    }
+
+    /// Create a nested entry from a path of keywords, such as `["Vec", "push"]`.\
+    /// Only the last level points at the topic
+    ///
+    /// Returns `None` if the path is empty
+    #[must_use]
+    pub fn nested(path: &[impl ToString], topic: ChmIndexTopic) -> Option<Self> {
+        let (last, parents) = path.split_last()?;
+        Some(Self::new(last, topic).with_parents(parents))
+    }
+
+    /// Nest this entry under a path of parent keywords, such as `["Vec"]`
+    #[must_use]
+    pub fn with_parents(self, parents: &[impl ToString]) -> Self {
+        let mut entry = self;
+        for parent in parents.iter().rev() {
+            entry = Self {
+                keyword: parent.to_string(),
+                topics: vec![],
+                see_also: None,
+                children: vec![entry],
+            };
+        }
+        entry
+    }
+
+    /// Add a sub-entry, merging it into any existing sub-entry with the same keyword, ignoring case
+    #[must_use]
+    pub fn with_child(mut self, child: ChmIndexEntry) -> Self {
+        self.insert_child(child);
+        self
+    }
+
+    /// Returns the entry with the given keywords removed from its children,
+    /// or `None` if it has one of them itself.\
+    /// Parents left without topics or children are removed as well
+    fn without_keywords(mut self, blocked: &HashSet<String>) -> Option<Self> {
+        if blocked.contains(&self.keyword.to_lowercase()) {
+            return None;
+        }
+
+        let had_children = !self.children.is_empty();
+        self.children = std::mem::take(&mut self.children)
+            .into_iter()
+            .filter_map(|child| child.without_keywords(blocked))
+            .collect();
+        if had_children
+            && self.children.is_empty()
+            && self.topics.is_empty()
+            && self.see_also.is_none()
+        {
+            return None;
+        }
+
+        Some(self)
+    }
+
+    fn insert_child(&mut self, child: ChmIndexEntry) {
+        match self
+            .children
+            .iter_mut()
+            .find(|existing| existing.keyword.to_lowercase() == child.keyword.to_lowercase())
+        {
+            Some(existing) => existing.merge(child),
+            None => self.children.push(child),
+        }
+    }
+
+    /// Merge another entry for the same keyword into this one
+    pub fn merge(&mut self, other: ChmIndexEntry) {
+        for topic in other.topics {
+            if !self
+                .topics
+                .iter()
+                .any(|existing| existing.file == topic.file)
+            {
+                self.topics.push(topic);
+            }
+        }
+
+        if self.see_also.is_none() {
+            self.see_also = other.see_also;
+        }
+
+        for child in other.children {
+            self.insert_child(child);
+        }
+    }
+
+    fn format(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        let indent = "    ".repeat(depth);
+        let mut params = vec![("Name", self.keyword.as_str())];
+        match (self.topics.as_slice(), &self.see_also) {
+            //
+            // Redirects replace any topics, which the viewer would ignore anyway
+            (_, Some(see_also)) => params.push(("See Also", see_also)),
+
+            //
+            // Entries without a topic must redirect somewhere, or the viewer reports an error
+            ([], None) => params.push(("See Also", &self.keyword)),
+
+            //
+            // A single topic needs no chooser
+            ([topic], None) => params.push(("Local", &topic.file)),
+
+            (topics, None) => {
+                for topic in topics {
+                    params.push(("Name", &topic.title));
+                    params.push(("Local", &topic.file));
+                }
+            }
+        }
+
+        write!(f, r#"{indent}<LI> <OBJECT type="text/sitemap">"#)?;
+        for (name, value) in params {
+            write!(f, r#"        <param name="{name}" value="{value}">"#)?;
+        }
+        write!(f, "        </OBJECT>")?;
+
+        if !self.children.is_empty() {
+            write!(f, "\n{indent}<UL>\n")?;
+            for child in &self.children {
+                child.format(f, depth + 1)?;
+                writeln!(f)?;
+            }
+            write!(f, "{indent}</UL>")?;
+        }
+
+        Ok(())
+    }
 }
 impl std::fmt::Display for ChmIndexEntry {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
+        self.format(f, 1)
+    }
+}
+
+/// A topic an index entry points at
+#[derive(Debug, Clone)]
+pub struct ChmIndexTopic {
+    /// Title shown in the "Topics Found" chooser
+    pub title: String,
+
+    /// Path of the topic, including any anchor
+    pub file: String,
+}
+impl ChmIndexTopic {
+    /// Create a new topic reference
+    #[must_use]
+    pub fn new(title: &impl ToString, file: &impl ToString) -> Self {
+        Self {
+            title: title.to_string(),
+            file: file.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(file: &str) -> ChmIndexTopic {
+        ChmIndexTopic::new(&file.to_uppercase(), &file)
+    }
+
+    #[test]
+    fn nests_merges_and_redirects() {
+        let mut index = ChmIndex::new();
+        index.insert(ChmIndexEntry::nested(&["Vec", "push"], topic("a.html")).unwrap());
+        index.insert(ChmIndexEntry::nested(&["vec", "capacity"], topic("b.html")).unwrap());
+        index.insert(ChmIndexEntry::nested(&["Vec", "Push"], topic("c.html")).unwrap());
+        index.insert(ChmIndexEntry::see_also(&"Arrays", &"Vec"));
+
+        let hhk = index.to_string();
+        let body = &hhk[hhk.find("<UL>").unwrap()..];
+        assert_eq!(
+            body,
             concat!(
-                r#"    <LI> <OBJECT type="text/sitemap">"#,
-                r#"        <param name="Name" value="{keyword}">"#,
-                r#"        <param name="Local" value="{file}">"#,
-                r#"        </OBJECT>"#,
-            ),
-            keyword = self.keyword,
-            file = self.file,
-        )
+                "<UL>\n",
+                r#"    <LI> <OBJECT type="text/sitemap">        <param name="Name" value="Vec">        <param name="See Also" value="Vec">        </OBJECT>"#,
+                "\n    <UL>\n",
+                r#"        <LI> <OBJECT type="text/sitemap">        <param name="Name" value="push">"#,
+                r#"        <param name="Name" value="A.HTML">        <param name="Local" value="a.html">"#,
+                r#"        <param name="Name" value="C.HTML">        <param name="Local" value="c.html">        </OBJECT>"#,
+                "\n",
+                r#"        <LI> <OBJECT type="text/sitemap">        <param name="Name" value="capacity">        <param name="Local" value="b.html">        </OBJECT>"#,
+                "\n    </UL>\n",
+                r#"    <LI> <OBJECT type="text/sitemap">        <param name="Name" value="Arrays">        <param name="See Also" value="Vec">        </OBJECT>"#,
+                "\n</UL>\n</BODY></HTML>"
+            )
+        );
+    }
+
+    #[test]
+    fn merged_topics_are_not_repeated() {
+        let mut index = ChmIndex::new();
+        index.insert(ChmIndexEntry::new(&"Vec", topic("a.html")));
+        index.insert(ChmIndexEntry::new(&"VEC", topic("a.html")));
+        assert_eq!(index.entries().len(), 1);
+        assert_eq!(index.entries()[0].topics.len(), 1);
+        assert!(
+            index
+                .to_string()
+                .contains(r#"<param name="Local" value="a.html">"#)
+        );
+    }
+
+    #[test]
+    fn removing_keywords_prunes_empty_parents() {
+        let mut index = ChmIndex::new();
+        index.insert(ChmIndexEntry::nested(&["Vec", "push"], topic("a.html")).unwrap());
+        index.insert(ChmIndexEntry::nested(&["Box", "new"], topic("b.html")).unwrap());
+        index.insert(ChmIndexEntry::nested(&["Box", "leak"], topic("b.html")).unwrap());
+        index.remove_keywords(&HashSet::from(["push".to_string(), "leak".to_string()]));
+
+        let keywords = index
+            .entries()
+            .iter()
+            .map(|entry| entry.keyword.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keywords, ["Box"]);
+        assert_eq!(index.entries()[0].children.len(), 1);
     }
 }
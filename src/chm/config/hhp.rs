@@ -24,6 +24,18 @@ pub struct ChmProject {
 
     /// Default file when opening
     pub default_file: String,
+
+    /// User-controllable compiler options
+    pub options: ChmOptions,
+
+    /// Path to the text popups file, if there are any popups
+    pub popups_path: Option<String>,
+
+    /// Path to the header mapping popup IDs to context numbers
+    pub context_header_path: Option<String>,
+
+    /// Files to compile in, which are not reachable from the TOC or index
+    pub files: Vec<String>,
 }
 impl std::fmt::Display for ChmProject {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -33,34 +45,339 @@ impl std::fmt::Display for ChmProject {
         let index_path = &self.index_path;
         let contents_path = &self.contents_path;
         let default_file = &self.default_file;
+        let options = &self.options;
+
+        let mut lines = vec![
+            "[OPTIONS]".to_string(),
+            format!("Binary TOC={}", yes_no(options.binary_toc)),
+        ];
+        if let Some(value) = options.binary_index {
+            lines.push(format!("Binary Index={}", yes_no(value)));
+        }
+        if let Some(value) = options.auto_index {
+            lines.push(format!("Auto Index={}", yes_no(value)));
+        }
+        lines.extend([
+            format!("Compatibility={}", options.compatibility),
+            format!("Compiled file={output_path}"),
+            format!("Contents file={contents_path}"),
+        ]);
+        if let Some(value) = options.create_chi_file {
+            lines.push(format!("Create CHI file={}", yes_no(value)));
+        }
+        if let Some(font) = &options.default_font {
+            lines.push(format!("Default Font={font}"));
+        }
+        lines.extend([
+            format!("Default topic={default_file}"),
+            format!(
+                "Display compile progress={}",
+                yes_no(options.display_compile_progress)
+            ),
+        ]);
+        if let Some(value) = options.display_compile_notes {
+            lines.push(format!("Display compile notes={}", yes_no(value)));
+        }
+        lines.push(format!(
+            "Enhanced decompilation={}",
+            yes_no(options.enhanced_decompilation)
+        ));
+        if let Some(path) = &options.error_log_file {
+            lines.push(format!("Error log file={path}"));
+        }
+        if let Some(value) = options.flat {
+            lines.push(format!("Flat={}", yes_no(value)));
+        }
+        lines.push(format!(
+            "Full-text search={}",
+            yes_no(options.full_text_search)
+        ));
+        if let Some(path) = &options.full_text_search_stop_list_file {
+            lines.push(format!("Full text search stop list file={path}"));
+        }
+        lines.extend([
+            format!("Index file={index_path}"),
+            format!("Language={language}"),
+        ]);
+        if let Some(path) = &options.sample_list_file {
+            lines.push(format!("Sample list file={path}"));
+        }
+        if let Some(path) = &options.sample_staging_path {
+            lines.push(format!("Sample Staging Path={path}"));
+        }
+        lines.push(format!("Title={title}"));
+        lines.push(String::new());
+
+        if let (Some(popups_path), Some(header_path)) =
+            (&self.popups_path, &self.context_header_path)
+        {
+            lines.extend([
+                "[MAP]".to_string(),
+                format!("#include {header_path}"),
+                String::new(),
+                "[TEXT POPUPS]".to_string(),
+                header_path.clone(),
+                popups_path.clone(),
+                String::new(),
+            ]);
+        }
+
+        lines.push("[FILES]".to_string());
+        lines.extend(self.files.iter().cloned());
 
         write!(
             f,
-            concat!(
-                "[OPTIONS]\n",
-                "Binary TOC=Yes\n",
-                "Compatibility=1.1 or later\n",
-                "Compiled file={output_path}\n",
-                "Contents file={contents_path}\n",
-                "Default topic={default_file}\n",
-                "Display compile progress=No\n",
-                "Enhanced decompilation=Yes\n",
-                "Full-text search=Yes\n",
-                "Index file={index_path}\n",
-                "Language={language}\n",
-                "Title={title}\n",
-                "\n",
-                "\n",
-                "[FILES]\n",
-                "\n",
-                "[INFOTYPES]"
-            ),
-            output_path = output_path,
-            contents_path = contents_path,
-            index_path = index_path,
-            language = language,
-            title = title,
-            default_file = default_file,
+            concat!("{options}\n", "\n", "[INFOTYPES]"),
+            options = lines.join("\n"),
+        )
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "Yes" } else { "No" }
+}
+
+/// The overridable parts of the `[OPTIONS]` section of the project
+///
+/// Fields left as `None` are not written, and the compiler's own default is used
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ChmOptions {
+    /// Compile the TOC into a binary format. Required for very large TOCs
+    pub binary_toc: bool,
+
+    /// Compile the index into a binary format
+    pub binary_index: Option<bool>,
+
+    /// Compile `KLink` keywords found in topics into the index
+    pub auto_index: Option<bool>,
+
+    /// The oldest viewer the file must remain compatible with
+    pub compatibility: ChmCompatibility,
+
+    /// Generate a separate .chi file for the index
+    pub create_chi_file: Option<bool>,
+
+    /// The font used for the TOC, index and search tabs
+    pub default_font: Option<ChmFont>,
+
+    /// Have the compiler print progress as it runs
+    pub display_compile_progress: bool,
+
+    /// Have the compiler print notes as it runs
+    pub display_compile_notes: Option<bool>,
+
+    /// Allow the resulting file to be decompiled
+    pub enhanced_decompilation: bool,
+
+    /// Path to a file where compiler errors get logged
+    pub error_log_file: Option<String>,
+
+    /// Store all files at the root of the compiled file, instead of keeping the folder structure
+    pub flat: Option<bool>,
+
+    /// Build the full-text search index
+    pub full_text_search: bool,
+
+    /// Path to a list of words excluded from the full-text search index
+    pub full_text_search_stop_list_file: Option<String>,
+
+    /// Path to the list of sample files for the viewer to copy out
+    pub sample_list_file: Option<String>,
+
+    /// Path to the folder holding the sample files
+    pub sample_staging_path: Option<String>,
+}
+impl Default for ChmOptions {
+    fn default() -> Self {
+        Self {
+            binary_toc: true,
+            binary_index: None,
+            auto_index: None,
+            compatibility: ChmCompatibility::default(),
+            create_chi_file: None,
+            default_font: None,
+            display_compile_progress: false,
+            display_compile_notes: None,
+            enhanced_decompilation: true,
+            error_log_file: None,
+            flat: None,
+            full_text_search: true,
+            full_text_search_stop_list_file: None,
+            sample_list_file: None,
+            sample_staging_path: None,
+        }
+    }
+}
+
+/// The compatibility level of a compiled file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ChmCompatibility {
+    /// Compatible with the 1.0 viewer
+    #[serde(rename = "1.0")]
+    V1_0,
+
+    /// Requires the 1.1 viewer or later
+    #[default]
+    #[serde(rename = "1.1 or later", alias = "1.1")]
+    V1_1,
+}
+impl std::fmt::Display for ChmCompatibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::V1_0 => write!(f, "1.0"),
+            Self::V1_1 => write!(f, "1.1 or later"),
+        }
+    }
+}
+
+/// A font description, in the `face,size,charset` form the compiler expects
+///
+/// For example `Tahoma,8,0`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ChmFont {
+    /// Name of the font face
+    pub face: String,
+
+    /// Point size
+    pub size: u32,
+
+    /// Windows character set number (0 for ANSI)
+    pub charset: u32,
+}
+impl std::str::FromStr for ChmFont {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(',').map(str::trim).collect::<Vec<_>>();
+        let [face, size, charset] = parts.as_slice() else {
+            return Err(format!(
+                "Invalid font `{s}`: expected `face,size,charset`, like `Tahoma,8,0`"
+            ));
+        };
+
+        if face.is_empty() {
+            return Err(format!("Invalid font `{s}`: the font face is empty"));
+        }
+        let size = size
+            .parse()
+            .map_err(|_| format!("Invalid font `{s}`: `{size}` is not a valid size"))?;
+        let charset = charset
+            .parse()
+            .map_err(|_| format!("Invalid font `{s}`: `{charset}` is not a valid charset"))?;
+
+        Ok(Self {
+            face: (*face).to_string(),
+            size,
+            charset,
+        })
+    }
+}
+impl TryFrom<String> for ChmFont {
+    type Error = String;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+impl From<ChmFont> for String {
+    fn from(value: ChmFont) -> Self {
+        value.to_string()
+    }
+}
+impl std::fmt::Display for ChmFont {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{}", self.face, self.size, self.charset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(options: ChmOptions) -> String {
+        ChmProject {
+            title: "Book".to_string(),
+            language: ChmLanguage::default(),
+            output_path: "book.chm".to_string(),
+            index_path: "index.hhk".to_string(),
+            contents_path: "contents.hhc".to_string(),
+            default_file: "src\\index.html".to_string(),
+            options,
+            popups_path: None,
+            context_header_path: None,
+            files: vec![],
+        }
+        .to_string()
+    }
+
+    #[test]
+    fn writes_configured_options() {
+        let options: ChmOptions = toml::from_str(
+            r#"
+            binary-toc = false
+            binary-index = true
+            auto-index = false
+            compatibility = "1.0"
+            create-chi-file = true
+            default-font = "Tahoma, 8, 0"
+            display-compile-notes = true
+            error-log-file = "log.txt"
+            flat = true
+            full-text-search = false
+            full-text-search-stop-list-file = "stop.stp"
+            sample-list-file = "samples.txt"
+            sample-staging-path = "samples"
+            "#,
         )
+        .unwrap();
+        let hhp = project(options);
+        for line in [
+            "Binary TOC=No",
+            "Binary Index=Yes",
+            "Auto Index=No",
+            "Compatibility=1.0",
+            "Create CHI file=Yes",
+            "Default Font=Tahoma,8,0",
+            "Display compile notes=Yes",
+            "Error log file=log.txt",
+            "Flat=Yes",
+            "Full-text search=No",
+            "Full text search stop list file=stop.stp",
+            "Sample list file=samples.txt",
+            "Sample Staging Path=samples",
+        ] {
+            assert!(hhp.lines().any(|l| l == line), "missing `{line}` in {hhp}");
+        }
+    }
+
+    #[test]
+    fn defaults_leave_unset_options_out() {
+        let hhp = project(ChmOptions::default());
+        for line in [
+            "Binary TOC=Yes",
+            "Compatibility=1.1 or later",
+            "Enhanced decompilation=Yes",
+            "Full-text search=Yes",
+        ] {
+            assert!(hhp.lines().any(|l| l == line), "missing `{line}` in {hhp}");
+        }
+        for option in ["Binary Index=", "Auto Index=", "Default Font=", "Flat="] {
+            assert!(!hhp.contains(option));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_bad_values() {
+        let options = |source: &str| {
+            toml::from_str::<ChmOptions>(source)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(options("binary-tox = true").contains("unknown field `binary-tox`"));
+        assert!(options("compatibility = \"2.0\"").contains("unknown variant `2.0`"));
+        assert!(options("default-font = \"Tahoma,big,0\"").contains("`big` is not a valid size"));
+        assert!(options("flat = \"yes\"").contains("invalid type"));
     }
 }
@@ -1,18 +1,19 @@
 macro_rules! define_langs {
     {
         $(
-            [ $ident:ident, $hexcode:literal, $name:literal, $shortcode:literal ]
+            $(#[$meta:meta])* [ $ident:ident, $hexcode:literal, $name:literal, $shortcode:literal ]
         ),+ $(,)?
     } => {
-            /// The set of language codes accepted by the CHM compiler.  
+            /// The set of language codes accepted by the CHM compiler.
             /// Each entry has a hex code, and a name ([`ChmLanguage::name`]).
             ///
             /// For the full list, see <https://www.w3.org/International/ms-lang.html>
-            #[derive(Debug, Clone, Copy)]
+            #[derive(Debug, Clone, Copy, Default)]
             #[repr(u32)]
             pub enum ChmLanguage {
                 $(
                     #[doc = concat!($name, "(", $shortcode, ")")]
+                    $(#[$meta])*
                     $ident = $hexcode
                 ),+
             }
@@ -26,6 +27,23 @@ macro_rules! define_langs {
                     }
                 }
 
+                /// Returns the shortcode of the language, like `en-us`
+                #[must_use]
+                pub fn code(&self) -> &'static str {
+                    match self {
+                        $(
+                            Self::$ident => $shortcode,
+                        )+
+                    }
+                }
+
+                /// Returns the primary language subtag, like `en` for `en-us`
+                #[must_use]
+                pub fn primary_code(&self) -> &'static str {
+                    let code = self.code();
+                    code.split('-').next().unwrap_or(code)
+                }
+
                 /// Returns the language for the given shortcode. Case insensitive
                 #[must_use]
                 pub fn from_code(shortcode: &str) -> Option<Self> {
@@ -45,11 +63,11 @@ macro_rules! define_langs {
                     write!(f, "{code:0x} {name}")
                 }
             }
-            
+
         }
 }
 
-define_langs!{
+define_langs! {
     [Af, 0x436, "Afrikaans", "af"],
     [Sq, 0x41c, "Albanian", "sq"],
     [Ar, 0x1, "Arabic (Standard)", "ar"],
@@ -84,7 +102,7 @@ define_langs!{
     [Nl, 0x413, "Dutch (Standard)", "nl"],
     [NlBe, 0x813, "Dutch (Belgian)", "nl-be"],
     [En, 0x9, "English", "en"],
-    [EnUs, 0x409, "English (United States)", "en-us"],
+    #[default] [EnUs, 0x409, "English (United States)", "en-us"],
     [EnGb, 0x809, "English (British)", "en-gb"],
     [EnAu, 0x0c09, "English (Australian)", "en-au"],
     [EnCa, 0x1009, "English (Canadian)", "en-ca"],
@@ -170,8 +188,3 @@ define_langs!{
     [Ji, 0x43d, "Yiddish", "ji"],
     [Zu, 0x435, "Zulu", "zu"],
 }
-impl Default for ChmLanguage {
-    fn default() -> Self {
-        Self::EnUs
-    }
-}
\ No newline at end of file
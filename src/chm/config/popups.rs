@@ -0,0 +1,225 @@
+//! Text popups (.txt) hold the short, plain-text help shown for individual dialog controls.
+//! Applications display them with `HH_DISPLAY_TEXT_POPUP`, using the context numbers from the exported header (.h) file.
+use std::collections::{BTreeMap, HashSet};
+
+/// All the text popups in the CHM project
+#[derive(Debug, Clone, Default)]
+pub struct ChmTextPopups(pub Vec<ChmTextPopup>);
+impl ChmTextPopups {
+    /// Parse a popups file in the following format:
+    /// ```toml
+    /// IDH_SAVE_BUTTON = "Saves the current document"
+    /// IDH_OPEN_BUTTON = { text = "Opens an existing document", context = 1002 }
+    /// ```
+    ///
+    /// # Errors
+    /// Will return an error if the file is not valid TOML, or an entry is malformed
+    pub fn from_toml(source: &str) -> std::io::Result<Self> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Entry {
+            Text(String),
+            Full { text: String, context: Option<u32> },
+        }
+
+        let entries: BTreeMap<String, Entry> = toml::from_str(source)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+
+        let mut popups = vec![];
+        for (id, entry) in entries {
+            let (text, context) = match entry {
+                Entry::Text(text) => (text, None),
+                Entry::Full { text, context } => (text, context),
+            };
+            popups.push(ChmTextPopup::new(&id, &text, context)?);
+        }
+
+        Ok(Self(popups))
+    }
+
+    /// Assigns a context number to every popup without one, and checks for duplicates.\
+    /// Numbers are derived from the popup ID, so adding or reordering popups does not change the others.
+    /// See [`ChmTextPopup::derived_context`]
+    ///
+    /// # Errors
+    /// Will return an error if an ID or context number is used twice
+    pub fn assign_contexts(&mut self) -> std::io::Result<()> {
+        let mut ids = HashSet::new();
+        let mut contexts = HashSet::new();
+        for popup in &self.0 {
+            if !ids.insert(popup.id.as_str()) {
+                return Err(duplicate_error(&format!("popup ID `{}`", popup.id)));
+            }
+            if let Some(context) = popup.context
+                && !contexts.insert(context)
+            {
+                return Err(duplicate_error(&format!(
+                    "popup context number `{context}`"
+                )));
+            }
+        }
+
+        for popup in &mut self.0 {
+            if popup.context.is_none() {
+                let context = popup.derived_context();
+                if !contexts.insert(context) {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "The context number `{context}` derived for popup `{}` is already used. Give it an explicit context number",
+                            popup.id
+                        ),
+                    ));
+                }
+                popup.context = Some(context);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the C header defining the context number of each popup
+    #[must_use]
+    pub fn header(&self) -> String {
+        let mut lines = vec![];
+        for popup in &self.0 {
+            lines.push(format!(
+                "#define {} {}",
+                popup.id,
+                popup.context.unwrap_or_default()
+            ));
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+impl std::fmt::Display for ChmTextPopups {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for popup in &self.0 {
+            writeln!(f, ".topic {}", popup.id)?;
+            writeln!(f, "{}", popup.text.trim())?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn duplicate_error(what: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("The {what} is used more than once"),
+    )
+}
+
+/// A single text popup
+#[derive(Debug, Clone)]
+pub struct ChmTextPopup {
+    /// The symbolic ID, used as the `#define` name in the header
+    pub id: String,
+
+    /// The context number passed to `HH_DISPLAY_TEXT_POPUP`.\
+    /// Derived from the ID when missing
+    pub context: Option<u32>,
+
+    /// Plain text contents of the popup
+    pub text: String,
+}
+impl ChmTextPopup {
+    /// Create a new popup
+    ///
+    /// # Errors
+    /// Will return an error if the ID is not a valid C identifier
+    pub fn new(
+        id: &impl ToString,
+        text: &impl ToString,
+        context: Option<u32>,
+    ) -> std::io::Result<Self> {
+        let id = id.to_string();
+        let mut chars = id.chars();
+        let valid = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Invalid popup ID `{id}`: it must be a valid C identifier, like `IDH_SAVE`"
+                ),
+            ));
+        }
+
+        Ok(Self {
+            id,
+            context,
+            text: text.to_string(),
+        })
+    }
+
+    /// The context number used when none is given: a hash of the ID, between `0x40000000` and `0x7FFFFFFF`.\
+    /// Explicit numbers below that range never clash with derived ones
+    #[must_use]
+    pub fn derived_context(&self) -> u32 {
+        //
+        // FNV-1a, which unlike the std hasher is stable between builds
+        let hash = self.id.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        });
+        0x4000_0000 | (hash & 0x3FFF_FFFF)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contexts(popups: &ChmTextPopups) -> BTreeMap<&str, u32> {
+        popups
+            .0
+            .iter()
+            .map(|popup| (popup.id.as_str(), popup.context.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn inserting_a_popup_keeps_the_other_contexts() {
+        let mut before = ChmTextPopups::from_toml(
+            "IDH_OPEN = \"Opens\"\nIDH_SAVE = \"Saves\"\nIDH_QUIT = { text = \"Quits\", context = 7 }",
+        )
+        .unwrap();
+        before.assign_contexts().unwrap();
+
+        let mut after = ChmTextPopups::from_toml(
+            "IDH_ABOUT = \"About\"\nIDH_OPEN = \"Opens\"\nIDH_SAVE = \"Saves\"\nIDH_QUIT = { text = \"Quits\", context = 7 }",
+        )
+        .unwrap();
+        after.0.reverse();
+        after.assign_contexts().unwrap();
+
+        let (before, after) = (contexts(&before), contexts(&after));
+        assert_eq!(after["IDH_QUIT"], 7);
+        for (id, context) in &before {
+            assert_eq!(after[id], *context);
+        }
+        assert!(after["IDH_OPEN"] >= 0x4000_0000);
+        assert_ne!(after["IDH_OPEN"], after["IDH_SAVE"]);
+    }
+
+    #[test]
+    fn duplicate_contexts_are_rejected() {
+        let mut popups = ChmTextPopups::from_toml(
+            "IDH_OPEN = { text = \"Opens\", context = 7 }\nIDH_SAVE = { text = \"Saves\", context = 7 }",
+        )
+        .unwrap();
+        assert!(popups.assign_contexts().is_err());
+
+        let derived = ChmTextPopup::new(&"IDH_OPEN", &"", None)
+            .unwrap()
+            .derived_context();
+        let mut popups = ChmTextPopups(vec![
+            ChmTextPopup::new(&"IDH_OPEN", &"Opens", None).unwrap(),
+            ChmTextPopup::new(&"IDH_SAVE", &"Saves", Some(derived)).unwrap(),
+        ]);
+        assert!(popups.assign_contexts().is_err());
+    }
+}
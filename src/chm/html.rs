@@ -0,0 +1,108 @@
+//! Minimal HTML tokenizing, used to inspect the rendered pages
+//!
+//! Wraps the `html5ever` tokenizer, turning a document into a flat stream of [`HtmlEvent`]s
+use html5ever::tokenizer::{
+    BufferQueue, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerOpts,
+    states::RawKind,
+};
+
+/// A single piece of an HTML document
+#[derive(Debug, Clone, Copy)]
+pub enum HtmlEvent<'a> {
+    /// An opening tag, with its attributes as name/value pairs
+    Start {
+        /// Lowercase tag name
+        name: &'a str,
+
+        /// Attributes as name/value pairs
+        attrs: &'a [(String, String)],
+    },
+
+    /// A closing tag, or the end of a self-closing tag
+    End {
+        /// Lowercase tag name
+        name: &'a str,
+    },
+
+    /// A run of text between two tags, with entities decoded.\
+    /// Includes the raw contents of `<script>` and `<style>` elements
+    Text(&'a str),
+}
+
+/// Tokenize an HTML document, calling `handler` for every tag and text run in order
+pub fn walk_html(html: &str, handler: impl FnMut(HtmlEvent<'_>)) {
+    let sink = HtmlSink {
+        handler,
+        text: String::new(),
+    };
+    let mut tokenizer = Tokenizer::new(sink, TokenizerOpts::default());
+    let mut queue = BufferQueue::default();
+    queue.push_back(html.into());
+    let _ = tokenizer.feed(&mut queue);
+    tokenizer.end();
+}
+
+struct HtmlSink<F> {
+    handler: F,
+    text: String,
+}
+impl<F: FnMut(HtmlEvent<'_>)> HtmlSink<F> {
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            (self.handler)(HtmlEvent::Text(&self.text));
+            self.text.clear();
+        }
+    }
+}
+impl<F: FnMut(HtmlEvent<'_>)> TokenSink for HtmlSink<F> {
+    type Handle = ();
+
+    fn process_token(&mut self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        match token {
+            Token::CharacterTokens(text) => self.text.push_str(&text),
+            Token::TagToken(tag) => {
+                self.flush_text();
+
+                let name = tag.name.to_string();
+                match tag.kind {
+                    TagKind::StartTag => {
+                        let attrs = tag
+                            .attrs
+                            .iter()
+                            .map(|attr| (attr.name.local.to_string(), attr.value.to_string()))
+                            .collect::<Vec<_>>();
+                        (self.handler)(HtmlEvent::Start {
+                            name: &name,
+                            attrs: &attrs,
+                        });
+                        if tag.self_closing {
+                            (self.handler)(HtmlEvent::End { name: &name });
+                        }
+
+                        //
+                        // The tokenizer needs to be told about elements that hold raw text
+                        match name.as_str() {
+                            "script" => return TokenSinkResult::RawData(RawKind::ScriptData),
+                            "style" | "xmp" | "iframe" | "noembed" | "noframes" => {
+                                return TokenSinkResult::RawData(RawKind::Rawtext);
+                            }
+                            "textarea" | "title" => {
+                                return TokenSinkResult::RawData(RawKind::Rcdata);
+                            }
+                            _ => {}
+                        }
+                    }
+                    TagKind::EndTag => (self.handler)(HtmlEvent::End { name: &name }),
+                }
+            }
+            Token::CommentToken(_) | Token::DoctypeToken(_) | Token::EOFToken => self.flush_text(),
+            Token::NullCharacterToken | Token::ParseError(_) => {}
+        }
+
+        TokenSinkResult::Continue
+    }
+
+    fn end(&mut self) {
+        self.flush_text();
+    }
+}
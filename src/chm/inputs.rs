@@ -1,8 +1,92 @@
 //! All the included file parsers
 //!
 //! Each will load a type of file and render it as an HTML document
-//! 
-//! Currently only markdown is supported
+//!
+//! Markdown is rendered to HTML, and stylesheets have their references followed
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
+
+mod confinement;
+pub use confinement::{Confinement, SymlinkPolicy};
+
+mod css;
+pub use css::load as css_load;
+
+mod diagrams;
+pub use diagrams::DiagramRendering;
+
+mod highlight;
+pub use highlight::{HighlightMode, Highlighting};
+
+mod math;
+pub use math::MathRendering;
 
 mod md;
-pub use md::load as md_load;
\ No newline at end of file
+pub use md::load as md_load;
+
+mod urls;
+
+/// How rendered math and diagrams are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageFormat {
+    /// SVG images
+    #[default]
+    Svg,
+
+    /// PNG images, for viewers without SVG support
+    Png,
+
+    /// No rendering. Math is left as markup, and diagrams as code blocks
+    Off,
+}
+impl ImageFormat {
+    /// The file extension of the images, or `None` when nothing is rendered
+    #[must_use]
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Self::Png => Some("png"),
+            Self::Svg => Some("svg"),
+            Self::Off => None,
+        }
+    }
+}
+
+/// Options controlling how input files are rendered
+#[derive(Debug, Clone, Default)]
+pub struct InputOptions {
+    /// Replaces the text of the first heading in the document.\
+    /// `{title}` is substituted with the original heading, which is appended if missing
+    pub first_heading_format: Option<String>,
+
+    /// Collect the Rust identifiers in inline code and Rust code blocks, anchoring them in the document
+    pub code_identifiers: bool,
+
+    /// Paths of the chapters in the book, normalized with [`crate::chm::utilities::normalize_path`].\
+    /// Links to markdown files that are not chapters are reported
+    pub chapters: Option<Arc<HashSet<String>>>,
+
+    /// Root of the book sources, which asset paths starting with `/` are relative to.\
+    /// Assets that are not beside the document are also looked for here
+    pub source_root: Option<PathBuf>,
+
+    /// Where dependencies can be read from. Without it, any path is read
+    pub confinement: Option<Confinement>,
+
+    /// Syntax highlighting for code blocks
+    pub highlighting: Highlighting,
+
+    /// Prefix of the hidden lines in code blocks, by language.\
+    /// Rust blocks hide lines starting with `# ` unless given a prefix here
+    pub hidden_lines: HashMap<String, String>,
+
+    /// Rendering of math into images
+    pub math: MathRendering,
+
+    /// Rendering of diagram code blocks into images
+    pub diagrams: DiagramRendering,
+}
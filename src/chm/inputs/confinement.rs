@@ -0,0 +1,376 @@
+//! Keeps dependency reads inside the book
+//!
+//! Dependencies are named by their path in the project, relative to the book root.\
+//! Shared asset directories outside the book can be allowed, and are mounted in the project under `_shared/<name>`.\
+//! Directories sharing a name are told apart with a number, like `_shared/assets-2`.
+//! Other paths under `_shared` are read from the book, which cannot hold a folder of the same name as a mount
+use crate::chm::utilities::{MakeAbsolute, normalize_path};
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+};
+
+/// Folder of the project holding the allowed directories outside the book
+const SHARED_FOLDER: &str = "_shared";
+
+/// How symbolic links in dependency paths are treated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    /// Follow links, as long as they point inside the book or an allowed directory
+    #[default]
+    Confined,
+
+    /// Follow links wherever they point
+    Follow,
+
+    /// Never read through a link
+    Deny,
+}
+
+/// Where dependencies can be read from
+#[derive(Debug, Clone)]
+pub struct Confinement {
+    /// Root of the book. Every dependency must be inside it, or an allowed directory
+    pub root: PathBuf,
+
+    /// Directories outside the book that dependencies can also be read from
+    pub allowed: Vec<PathBuf>,
+
+    /// How symbolic links are treated
+    pub symlinks: SymlinkPolicy,
+}
+impl Confinement {
+    /// Confine dependencies to a book root, and a list of allowed directories.\
+    /// Relative paths are resolved against the current directory
+    #[must_use]
+    pub fn new(root: impl AsRef<Path>, allowed: &[PathBuf], symlinks: SymlinkPolicy) -> Self {
+        Self {
+            root: lexical_absolute(root.as_ref()),
+            allowed: allowed.iter().map(|path| lexical_absolute(path)).collect(),
+            symlinks,
+        }
+    }
+
+    /// The path in the project of a file outside the book, if it is in an allowed directory
+    #[must_use]
+    pub fn mount(&self, path: &Path) -> Option<PathBuf> {
+        let path = lexical_absolute(&self.root.join(path));
+        self.allowed
+            .iter()
+            .zip(self.mount_names())
+            .find_map(|(allowed, name)| {
+                let rest = path.strip_prefix(allowed).ok()?;
+                Some(Path::new(SHARED_FOLDER).join(name?).join(rest))
+            })
+    }
+
+    /// The folder name of each allowed directory under `_shared`.\
+    /// Names are unique: when directories share a name, the later ones get a number
+    fn mount_names(&self) -> Vec<Option<String>> {
+        let base_names = self
+            .allowed
+            .iter()
+            .map(|allowed| Some(allowed.file_name()?.to_string_lossy().into_owned()))
+            .collect::<Vec<_>>();
+
+        let mut used = HashSet::new();
+        base_names
+            .iter()
+            .map(|base| {
+                let base = base.as_ref()?;
+                let mut name = base.clone();
+                let mut number = 1;
+                while used.contains(&name)
+                    || (number > 1 && base_names.iter().flatten().any(|other| *other == name))
+                {
+                    number += 1;
+                    name = format!("{base}-{number}");
+                }
+                used.insert(name.clone());
+                Some(name)
+            })
+            .collect()
+    }
+
+    /// The file to read for a dependency, after checking it is allowed
+    ///
+    /// # Errors
+    /// Will return an error if the dependency is outside the book and the allowed directories,
+    /// goes through a symbolic link the policy forbids, or is mounted where the book has a folder of its own
+    pub fn source(&self, dependency: &Path) -> std::io::Result<PathBuf> {
+        let outside = |reason: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("`{}` {reason}", dependency.display()),
+            )
+        };
+
+        let Some(project_path) = normalize_path(dependency).filter(|_| dependency.is_relative())
+        else {
+            return Err(outside("is outside the book"));
+        };
+
+        //
+        // Files mounted from allowed directories come from there
+        let mut parts = project_path.splitn(3, '/');
+        let mount = match (parts.next(), parts.next(), parts.next()) {
+            (Some(SHARED_FOLDER), Some(name), rest) => self
+                .allowed
+                .iter()
+                .zip(self.mount_names())
+                .find(|(_, mount)| mount.as_deref() == Some(name))
+                .map(|(allowed, _)| (name, allowed, rest.unwrap_or_default())),
+            _ => None,
+        };
+        let (base, source) = match mount {
+            Some((name, allowed, rest)) => {
+                if self.root.join(SHARED_FOLDER).join(name).exists() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        format!(
+                            "`{SHARED_FOLDER}/{name}` is in the book, where `{}` is mounted. Rename the folder in the book",
+                            allowed.display()
+                        ),
+                    ));
+                }
+                (allowed, allowed.join(rest))
+            }
+            None => (&self.root, self.root.join(&project_path)),
+        };
+
+        //
+        // Missing files are reported when they are read
+        let Ok(canonical) = source.canonicalize() else {
+            return Ok(source);
+        };
+        match self.symlinks {
+            SymlinkPolicy::Follow => {}
+            SymlinkPolicy::Deny => {
+                if has_symlink(&source, base) {
+                    return Err(outside("goes through a symbolic link"));
+                }
+            }
+            SymlinkPolicy::Confined => {
+                let confined = std::iter::once(&self.root)
+                    .chain(&self.allowed)
+                    .filter_map(|dir| dir.canonicalize().ok())
+                    .any(|dir| canonical.starts_with(dir));
+                if !confined {
+                    return Err(outside("links outside the book"));
+                }
+            }
+        }
+
+        Ok(source)
+    }
+}
+
+/// An absolute path with `.` and `..` resolved, without touching the file system
+fn lexical_absolute(path: &Path) -> PathBuf {
+    let mut absolute = PathBuf::new();
+    for component in path.make_absolute().components() {
+        match component {
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            Component::CurDir => {}
+            component => absolute.push(component),
+        }
+    }
+    absolute
+}
+
+/// True if the path goes through a symbolic link below `base`
+fn has_symlink(path: &Path, base: &Path) -> bool {
+    path.ancestors()
+        .take_while(|ancestor| *ancestor != base && ancestor.starts_with(base))
+        .any(|ancestor| {
+            ancestor
+                .symlink_metadata()
+                .is_ok_and(|metadata| metadata.file_type().is_symlink())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    /// A temporary directory, removed when dropped
+    struct Fixture(PathBuf);
+    impl std::ops::Deref for Fixture {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    /// A fresh directory holding a `book`, an `outside` folder with a secret, and a `shared` folder with a logo
+    fn fixture(name: &str) -> Fixture {
+        let dir = std::env::temp_dir().join(format!(
+            "mdbook-chm-confinement-{name}-{}",
+            std::process::id()
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+        for folder in ["book/img", "outside", "shared"] {
+            std::fs::create_dir_all(dir.join(folder)).unwrap();
+        }
+        std::fs::write(dir.join("book/img/a.png"), "a").unwrap();
+        std::fs::write(dir.join("outside/secret.txt"), "secret").unwrap();
+        std::fs::write(dir.join("shared/logo.png"), "logo").unwrap();
+        Fixture(dir)
+    }
+
+    fn denied(result: std::io::Result<PathBuf>) -> bool {
+        result.is_err_and(|e| e.kind() == ErrorKind::PermissionDenied)
+    }
+
+    #[test]
+    fn rejects_parent_escapes() {
+        let dir = fixture("parent");
+        let confinement = Confinement::new(dir.join("book"), &[], SymlinkPolicy::default());
+
+        assert!(denied(
+            confinement.source(Path::new("../outside/secret.txt"))
+        ));
+        assert!(denied(
+            confinement.source(Path::new("img/../../outside/secret.txt"))
+        ));
+        assert_eq!(
+            confinement.source(Path::new("img/../img/a.png")).unwrap(),
+            dir.join("book/img/a.png")
+        );
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let dir = fixture("absolute");
+        let confinement = Confinement::new(dir.join("book"), &[], SymlinkPolicy::default());
+
+        assert!(denied(confinement.source(&dir.join("outside/secret.txt"))));
+        assert!(denied(confinement.source(&dir.join("book/img/a.png"))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_follow_the_policy() {
+        let dir = fixture("symlinks");
+        std::os::unix::fs::symlink(dir.join("outside"), dir.join("book/escape")).unwrap();
+        std::os::unix::fs::symlink(dir.join("book/img"), dir.join("book/inside")).unwrap();
+        let escape = Path::new("escape/secret.txt");
+        let inside = Path::new("inside/a.png");
+
+        let confined = Confinement::new(dir.join("book"), &[], SymlinkPolicy::Confined);
+        assert!(denied(confined.source(escape)));
+        assert!(confined.source(inside).is_ok());
+
+        let deny = Confinement::new(dir.join("book"), &[], SymlinkPolicy::Deny);
+        assert!(denied(deny.source(escape)));
+        assert!(denied(deny.source(inside)));
+
+        let follow = Confinement::new(dir.join("book"), &[], SymlinkPolicy::Follow);
+        assert!(follow.source(escape).is_ok());
+    }
+
+    #[test]
+    fn mounts_allowed_directories() {
+        let dir = fixture("mount");
+        let confinement = Confinement::new(
+            dir.join("book"),
+            &[dir.join("shared")],
+            SymlinkPolicy::default(),
+        );
+
+        let mounted = confinement.mount(Path::new("../shared/logo.png")).unwrap();
+        assert_eq!(mounted, Path::new("_shared/shared/logo.png"));
+        assert_eq!(
+            confinement.source(&mounted).unwrap(),
+            dir.join("shared/logo.png")
+        );
+
+        assert_eq!(confinement.mount(Path::new("../outside/secret.txt")), None);
+        assert_eq!(
+            confinement
+                .source(Path::new("_shared/outside/secret.txt"))
+                .unwrap(),
+            dir.join("book/_shared/outside/secret.txt")
+        );
+        assert!(denied(confinement.source(Path::new(
+            "_shared/shared/../../../outside/secret.txt"
+        ))));
+    }
+
+    #[test]
+    fn mounts_directories_sharing_a_name_apart() {
+        let dir = fixture("same-name");
+        for (folder, contents) in [("a/assets", "a"), ("b/assets", "b"), ("c/assets-2", "c")] {
+            std::fs::create_dir_all(dir.join(folder)).unwrap();
+            std::fs::write(dir.join(folder).join("logo.png"), contents).unwrap();
+        }
+        let confinement = Confinement::new(
+            dir.join("book"),
+            &[
+                dir.join("a/assets"),
+                dir.join("b/assets"),
+                dir.join("c/assets-2"),
+            ],
+            SymlinkPolicy::default(),
+        );
+
+        for (path, mount, contents) in [
+            ("../a/assets/logo.png", "_shared/assets/logo.png", "a"),
+            ("../b/assets/logo.png", "_shared/assets-3/logo.png", "b"),
+            ("../c/assets-2/logo.png", "_shared/assets-2/logo.png", "c"),
+        ] {
+            let mounted = confinement.mount(Path::new(path)).unwrap();
+            assert_eq!(mounted, Path::new(mount));
+            let source = confinement.source(&mounted).unwrap();
+            assert_eq!(std::fs::read_to_string(source).unwrap(), contents);
+        }
+    }
+
+    #[test]
+    fn shared_folders_in_the_book_are_read_from_the_book() {
+        let dir = fixture("book-shared");
+        std::fs::create_dir_all(dir.join("book/_shared/notes")).unwrap();
+        std::fs::write(dir.join("book/_shared/notes/a.png"), "notes").unwrap();
+        let confinement = Confinement::new(
+            dir.join("book"),
+            &[dir.join("shared")],
+            SymlinkPolicy::default(),
+        );
+
+        assert_eq!(
+            confinement
+                .source(Path::new("_shared/notes/a.png"))
+                .unwrap(),
+            dir.join("book/_shared/notes/a.png")
+        );
+        assert_eq!(
+            confinement
+                .source(Path::new("_shared/shared/logo.png"))
+                .unwrap(),
+            dir.join("shared/logo.png")
+        );
+
+        //
+        // A folder of the book named like a mount would be hidden by it
+        std::fs::create_dir_all(dir.join("book/_shared/shared")).unwrap();
+        let error = confinement
+            .source(Path::new("_shared/shared/logo.png"))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert!(
+            error
+                .to_string()
+                .starts_with("`_shared/shared` is in the book")
+        );
+    }
+}
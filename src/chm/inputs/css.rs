@@ -0,0 +1,168 @@
+//! Stylesheets, whose `url(...)` and `@import` references are followed and rewritten like the images in a document
+use super::{InputOptions, urls::resolve_asset};
+use crate::chm::contents::{File, FileMetadata};
+use regex::{Captures, Regex};
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?i)url\(\s*(?:"([^"]*)"|'([^']*)'|([^)"'\s]*))\s*\)|@import\s+(?:"([^"]*)"|'([^']*)')"#,
+    )
+    .unwrap()
+});
+
+/// Loads a stylesheet, collecting the files it references
+///
+/// # Errors
+/// Can return an error if the source cannot be read
+pub fn load(
+    path: &Path,
+    contents: &[u8],
+    input_options: &InputOptions,
+) -> std::io::Result<(File, Vec<PathBuf>)> {
+    let contents = String::from_utf8_lossy(contents);
+    let mut dependencies = vec![];
+    let css = rewrite_urls(&contents, path, input_options, &mut dependencies);
+
+    let own_file = File {
+        path: path.to_path_buf(),
+        contents: css.into_bytes(),
+        metadata: FileMetadata::default(),
+    };
+    Ok((own_file, dependencies))
+}
+
+/// Rewrites the `url(...)` and `@import` references in CSS written in `document`, collecting the files they point at
+pub fn rewrite_urls(
+    css: &str,
+    document: &Path,
+    options: &InputOptions,
+    dependencies: &mut Vec<PathBuf>,
+) -> String {
+    URL_REGEX
+        .replace_all(css, |captures: &Captures<'_>| {
+            let original = &captures[0];
+            let (Some(all), Some(url)) = (captures.get(0), (1..=5).find_map(|i| captures.get(i)))
+            else {
+                return original.to_string();
+            };
+            let Some((asset, new_url)) = resolve_asset(document, url.as_str(), options) else {
+                return original.to_string();
+            };
+
+            if !dependencies.contains(&asset) {
+                dependencies.push(asset);
+            }
+            //
+            // Keep the original quotes, which might be inside an HTML attribute
+            let start = url.start() - all.start();
+            let end = url.end() - all.start();
+            format!("{}{new_url}{}", &original[..start], &original[end..])
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chm::{
+        contents::IncludedFiles,
+        inputs::{Confinement, SymlinkPolicy},
+    };
+
+    fn options() -> InputOptions {
+        InputOptions {
+            source_root: Some(PathBuf::from("src")),
+            ..InputOptions::default()
+        }
+    }
+
+    #[test]
+    fn rewrites_urls_and_imports() {
+        let css = concat!(
+            "@import \"/theme/base.css\";\n",
+            "@import 'print.css' print;\n",
+            "body { background: url( ../img/bg.png ) }\n",
+            "h1 { background: url(\"/img/h1.png\") }\n",
+            "i { background: url(data:image/png;base64,AAA), url('https://example.com/i.png') }\n"
+        );
+        let (file, dependencies) =
+            load(Path::new("src/theme/book.css"), css.as_bytes(), &options()).unwrap();
+        assert_eq!(
+            String::from_utf8(file.contents).unwrap(),
+            concat!(
+                "@import \"base.css\";\n",
+                "@import 'print.css' print;\n",
+                "body { background: url( ../img/bg.png ) }\n",
+                "h1 { background: url(\"../img/h1.png\") }\n",
+                "i { background: url(data:image/png;base64,AAA), url('https://example.com/i.png') }\n"
+            )
+        );
+        assert_eq!(
+            dependencies,
+            [
+                "src/theme/base.css",
+                "src/theme/print.css",
+                "src/img/bg.png",
+                "src/img/h1.png"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn stylesheet_dependencies_are_included() {
+        let root = std::env::temp_dir().join(format!("mdbook-chm-css-{}", std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+        for (path, contents) in [
+            (
+                "src/theme/book.css",
+                "@import 'fonts.css';\nbody { background: url(/img/bg.png) }",
+            ),
+            (
+                "src/theme/fonts.css",
+                "@import 'book.css';\n@font-face { src: url(../fonts/a.woff) }",
+            ),
+            ("src/fonts/a.woff", "font"),
+            ("src/img/bg.png", "image"),
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        let mut files = IncludedFiles::with_options(InputOptions {
+            confinement: Some(Confinement::new(&root, &[], SymlinkPolicy::default())),
+            ..options()
+        });
+        let result = files.add_file(
+            "src/a.md",
+            b"# A\n\n<link rel=\"stylesheet\" href=\"theme/book.css\">\n",
+        );
+        std::fs::remove_dir_all(&root).ok();
+        result.unwrap();
+
+        let paths = files
+            .files
+            .iter()
+            .map(|file| file.path.to_string_lossy().replace('\\', "/"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "src/fonts/a.woff",
+                "src/theme/fonts.css",
+                "src/img/bg.png",
+                "src/theme/book.css",
+                "src/a.html"
+            ]
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&files.files[3].contents),
+            "@import 'fonts.css';\nbody { background: url(../img/bg.png) }"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Diagram rendering, done when the book is built since the viewer cannot run the plugins of the web book
+//!
+//! Graphviz `dot` is laid out by `layout-rs`, and ASCII art by `svgbob`.\
+//! Diagrams are named after their source, and kept in a cache so unchanged diagrams are not rendered again.\
+//! Rendering needs the `diagrams` feature. Without it, diagrams are left as code blocks
+use super::ImageFormat;
+use crate::chm::utilities::content_hash;
+use std::path::PathBuf;
+
+#[cfg(feature = "diagrams")]
+mod render;
+
+/// Fence languages of diagrams
+const LANGUAGES: &[&str] = &["dot", "graphviz", "bob", "svgbob"];
+
+/// Versions of the renderers, part of the file names so an upgrade does not keep serving the old images from the cache
+const RENDERERS: &str = "layout-rs 0.1.3, svgbob 0.7.6, resvg 0.47.0";
+
+/// Diagram rendering settings
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DiagramRendering {
+    /// How diagrams are written
+    pub format: ImageFormat,
+
+    /// Pixels per point in PNG images
+    pub png_scale: f32,
+
+    /// Folder keeping rendered diagrams between builds, relative to the book root.\
+    /// Defaults to `cache` in the output folder
+    pub cache: Option<PathBuf>,
+}
+impl Default for DiagramRendering {
+    fn default() -> Self {
+        Self {
+            format: ImageFormat::default(),
+            png_scale: 2.0,
+            cache: None,
+        }
+    }
+}
+impl DiagramRendering {
+    /// True if code blocks in a language are diagrams to render
+    #[must_use]
+    pub fn is_diagram(&self, language: &str) -> bool {
+        self.format != ImageFormat::Off && LANGUAGES.contains(&language)
+    }
+
+    /// The file name of a diagram, after its content and the settings.\
+    /// `None` when diagram rendering is turned off
+    #[must_use]
+    pub fn file_name(&self, language: &str, source: &str) -> Option<String> {
+        let extension = self.format.extension()?;
+        let scale = match self.format {
+            ImageFormat::Png => self.png_scale,
+            ImageFormat::Svg | ImageFormat::Off => 1.0,
+        };
+        let key = format!("{RENDERERS}\n{language}\n{extension}\n{scale}\n{source}");
+        Some(format!("{}.{extension}", content_hash(key.as_bytes())))
+    }
+}
@@ -0,0 +1,325 @@
+//! Renders diagrams into SVG, and rasterizes them into PNG
+use super::{DiagramRendering, ImageFormat};
+use layout::{
+    backends::svg::SVGWriter,
+    gv::{DotParser, GraphBuilder, parser::ast},
+};
+use resvg::usvg::{self, fontdb};
+use std::{
+    collections::HashMap,
+    panic::{AssertUnwindSafe, catch_unwind},
+    path::Path,
+    sync::{Arc, LazyLock},
+};
+
+/// Fonts used for the text of PNG diagrams
+static FONTS: LazyLock<Arc<fontdb::Database>> = LazyLock::new(|| {
+    let mut fonts = fontdb::Database::new();
+    for data in typst_assets::fonts() {
+        fonts.load_font_data(data.to_vec());
+    }
+    fonts.set_serif_family("Libertinus Serif");
+    fonts.set_sans_serif_family("Libertinus Serif");
+    fonts.set_monospace_family("DejaVu Sans Mono");
+    Arc::new(fonts)
+});
+
+impl DiagramRendering {
+    /// Render a diagram, or read it from the cache
+    ///
+    /// # Errors
+    /// Will return a description of the problem if the diagram cannot be rendered, or if diagram rendering is turned off
+    pub fn render(&self, language: &str, source: &str) -> Result<RenderedDiagram, String> {
+        let name = self
+            .file_name(language, source)
+            .ok_or_else(|| "Diagram rendering is turned off".to_string())?;
+        let cached = self.cache.as_ref().map(|cache| cache.join(name));
+        if let Some(path) = &cached
+            && let Ok(image) = std::fs::read(path)
+            && let Some(diagram) = self.measure(image)
+        {
+            return Ok(diagram);
+        }
+
+        let svg = render_svg(language, source)?;
+        let image = match self.format {
+            ImageFormat::Png => rasterize(&svg, self.png_scale)?,
+            ImageFormat::Svg => svg.into_bytes(),
+            ImageFormat::Off => unreachable!("diagrams are not rendered when turned off"),
+        };
+        if let Some(path) = &cached {
+            cache_write(path, &image);
+        }
+        self.measure(image)
+            .ok_or_else(|| "The rendered diagram is not a valid image".to_string())
+    }
+
+    /// The size of an image, in CSS pixels
+    fn measure(&self, image: Vec<u8>) -> Option<RenderedDiagram> {
+        let (width, height) = match self.format {
+            ImageFormat::Png => {
+                let width = u32::from_be_bytes(image.get(16..20)?.try_into().ok()?);
+                let height = u32::from_be_bytes(image.get(20..24)?.try_into().ok()?);
+                (
+                    f64::from(width) / f64::from(self.png_scale),
+                    f64::from(height) / f64::from(self.png_scale),
+                )
+            }
+            ImageFormat::Off => return None,
+            ImageFormat::Svg => {
+                let tree = usvg::Tree::from_data(&image, &usvg::Options::default()).ok()?;
+                (
+                    f64::from(tree.size().width()),
+                    f64::from(tree.size().height()),
+                )
+            }
+        };
+        Some(RenderedDiagram {
+            image,
+            width,
+            height,
+        })
+    }
+}
+
+/// A diagram rendered into an image
+#[derive(Debug, Clone)]
+pub struct RenderedDiagram {
+    /// The image file
+    pub image: Vec<u8>,
+
+    /// Width of the image, in CSS pixels
+    pub width: f64,
+
+    /// Height of the image, in CSS pixels
+    pub height: f64,
+}
+
+/// Render a diagram into SVG
+fn render_svg(language: &str, source: &str) -> Result<String, String> {
+    match language {
+        "bob" | "svgbob" => Ok(svgbob::to_svg(source)),
+        _ => {
+            let mut parser = DotParser::new(source);
+            let graph = parser.process()?;
+            check_graph(&graph)?;
+
+            //
+            // The graph is checked for the input the layout is known to panic on.
+            // Anything else it panics on fails this diagram, rather than the whole build
+            let svg = catch_unwind(AssertUnwindSafe(|| {
+                let mut builder = GraphBuilder::new();
+                builder.visit_graph(&graph);
+                let mut visual = builder.get();
+                let mut svg = SVGWriter::new();
+                visual.do_it(false, false, false, &mut svg);
+                svg.finalize()
+            }))
+            .map_err(|_| "The graph could not be laid out".to_string())?;
+
+            //
+            // Arrow heads take the colour of their edge with `context-stroke`, which the viewer does not support
+            Ok(svg.replace("fill=\"context-stroke\"", "fill=\"black\""))
+        }
+    }
+}
+
+/// Reject the graphs the layout cannot handle, before it panics on them:
+/// graphs without nodes, and record nodes with a malformed label
+fn check_graph(graph: &ast::Graph) -> Result<(), String> {
+    let mut nodes = HashMap::new();
+    collect_nodes(graph, HashMap::new(), &mut nodes);
+    if nodes.is_empty() {
+        return Err("The graph has no nodes".to_string());
+    }
+
+    for (name, attributes) in &nodes {
+        let is_record = attributes
+            .get("shape")
+            .is_some_and(|shape| matches!(*shape, "record" | "Mrecord"));
+        let label = attributes.get("label").copied().unwrap_or(name);
+        if is_record {
+            check_record_label(label)
+                .map_err(|problem| format!("The record label of node `{name}` {problem}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Check a record label is made of balanced `{...}` groups of `|` separated fields, with closed `<port>` names.\
+/// Like the layout, backslashes do not escape anything
+fn check_record_label(label: &str) -> Result<(), &'static str> {
+    if label.is_empty() {
+        return Err("is empty");
+    }
+
+    let mut depth = 0_usize;
+    let mut in_port = false;
+    for c in label.chars() {
+        match c {
+            '>' if in_port => in_port = false,
+            '{' | '}' | '|' | '<' if in_port => return Err("has an unclosed `<port>`"),
+            '<' => in_port = true,
+            '{' => depth += 1,
+            '}' => depth = depth.checked_sub(1).ok_or("has an unmatched `}`")?,
+            _ => {}
+        }
+    }
+
+    if in_port {
+        Err("has an unclosed `<port>`")
+    } else if depth > 0 {
+        Err("has an unmatched `{`")
+    } else {
+        Ok(())
+    }
+}
+
+/// The attributes of every node in a graph, with the `node [...]` defaults in effect where it first appears
+fn collect_nodes<'a>(
+    graph: &'a ast::Graph,
+    mut defaults: HashMap<&'a str, &'a str>,
+    nodes: &mut HashMap<&'a str, HashMap<&'a str, &'a str>>,
+) {
+    for statement in &graph.list.list {
+        match statement {
+            ast::Stmt::Edge(edge) => {
+                for id in std::iter::once(&edge.from).chain(edge.to.iter().map(|(id, _)| id)) {
+                    nodes
+                        .entry(id.name.as_str())
+                        .or_insert_with(|| defaults.clone());
+                }
+            }
+            ast::Stmt::Node(node) => {
+                let attributes = nodes
+                    .entry(node.id.name.as_str())
+                    .or_insert_with(|| defaults.clone());
+                attributes.extend(node.list.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+            }
+            ast::Stmt::Attribute(ast::AttrStmt {
+                target: ast::AttrStmtTarget::Node,
+                list,
+            }) => {
+                defaults.extend(list.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+            }
+            ast::Stmt::Attribute(_) => {}
+            ast::Stmt::SubGraph(subgraph) => collect_nodes(subgraph, defaults.clone(), nodes),
+        }
+    }
+}
+
+/// Convert an SVG diagram into a PNG image
+fn rasterize(svg: &str, scale: f32) -> Result<Vec<u8>, String> {
+    let options = usvg::Options {
+        fontdb: FONTS.clone(),
+        ..usvg::Options::default()
+    };
+    let tree = usvg::Tree::from_str(svg, &options).map_err(|e| e.to_string())?;
+
+    let size = tree
+        .size()
+        .to_int_size()
+        .scale_by(scale)
+        .ok_or("The diagram is empty")?;
+    let mut pixmap =
+        resvg::tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("The diagram is empty")?;
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+/// Keep a rendered diagram for the next build. A cache that cannot be written only costs time
+fn cache_write(path: &Path, image: &[u8]) {
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(path, image));
+    if let Err(e) = written {
+        eprintln!("Warning: Could not cache `{}`: {e}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_graphs_the_layout_cannot_handle() {
+        assert!(render_svg("dot", "digraph {}").is_err());
+        assert!(render_svg("dot", "digraph { subgraph s {} }").is_err());
+        assert!(render_svg("dot", r#"digraph { a [shape=record, label=""] }"#).is_err());
+        assert!(render_svg("dot", "digraph { node [shape=Mrecord]; a -> \"b{\" }").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_record_labels() {
+        for label in ["", "a{", "{a|b", "a|b}", "}", "{a}}", "<p", "<p|a", "{<p}"] {
+            assert!(check_record_label(label).is_err(), "{label:?} was accepted");
+        }
+        for label in ["a", "a|b", "{a|{<p> b|c}}|d", "<p>", "{}", "|"] {
+            assert_eq!(check_record_label(label), Ok(()), "{label:?} was rejected");
+        }
+
+        let graph = r#"digraph { a [shape=record, label="{x|y"]; a -> b }"#;
+        assert_eq!(
+            render_svg("dot", graph).unwrap_err(),
+            "The record label of node `a` has an unmatched `{`"
+        );
+    }
+
+    #[test]
+    fn node_defaults_do_not_override_earlier_attributes() {
+        let graph = DotParser::new(
+            r#"digraph { a [shape=box, label="{"]; node [shape=record]; a [color=red]; b }"#,
+        )
+        .process()
+        .unwrap();
+        let mut nodes = HashMap::new();
+        collect_nodes(&graph, HashMap::new(), &mut nodes);
+
+        assert_eq!(nodes["a"]["shape"], "box");
+        assert_eq!(nodes["a"]["color"], "red");
+        assert_eq!(nodes["b"]["shape"], "record");
+        assert!(check_graph(&graph).is_ok());
+    }
+
+    #[test]
+    fn renders_graphs() {
+        let svg = render_svg(
+            "dot",
+            r#"digraph { a [shape=record, label="{x|y}"]; a -> b }"#,
+        );
+        assert!(svg.is_ok_and(|svg| svg.contains("<svg")));
+    }
+
+    #[test]
+    fn renderer_versions_match_the_lock_file() {
+        let Ok(lock) = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.lock"))
+        else {
+            return;
+        };
+        for renderer in crate::chm::inputs::diagrams::RENDERERS.split(", ") {
+            let (name, version) = renderer.split_once(' ').unwrap();
+            assert!(
+                lock.contains(&format!("name = \"{name}\"\nversion = \"{version}\"\n")),
+                "`{name}` is no longer at {version}, update `RENDERERS`"
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_to_render_when_turned_off() {
+        let options = DiagramRendering {
+            format: ImageFormat::Off,
+            ..DiagramRendering::default()
+        };
+        assert_eq!(options.file_name("dot", "digraph { a -> b }"), None);
+        assert_eq!(
+            options.render("dot", "digraph { a -> b }").unwrap_err(),
+            "Diagram rendering is turned off"
+        );
+    }
+}
@@ -0,0 +1,299 @@
+//! Syntax highlighting for code blocks, done when the book is built since the viewer cannot run scripts
+//!
+//! Code is highlighted into spans with inline styles, which every document mode of the viewer supports,
+//! or into classes backed by a stylesheet for the theme
+use comrak::adapters::SyntaxHighlighterAdapter;
+use std::{collections::HashMap, io::Write, sync::LazyLock};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Color, Theme, ThemeSet},
+    html::{
+        ClassStyle, ClassedHTMLGenerator, IncludeBackground,
+        append_highlighted_html_for_styled_line, css_for_theme_with_class_style,
+    },
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Prefix of the classes used with [`HighlightMode::Classes`]
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// How highlighted code is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HighlightMode {
+    /// Spans with inline styles
+    #[default]
+    Inline,
+
+    /// Spans with classes, and a stylesheet for the theme in each page
+    Classes,
+
+    /// No highlighting
+    Off,
+}
+
+/// Syntax highlighting settings
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Highlighting {
+    /// How highlighted code is written
+    pub mode: HighlightMode,
+
+    /// Name of the theme, like `InspiredGitHub` or `Solarized (light)`
+    pub theme: String,
+
+    /// Language of code blocks without one. Default is `rust`, like mdbook
+    pub default_language: String,
+}
+impl Default for Highlighting {
+    fn default() -> Self {
+        Self {
+            mode: HighlightMode::default(),
+            theme: "InspiredGitHub".to_string(),
+            default_language: "rust".to_string(),
+        }
+    }
+}
+impl Highlighting {
+    /// Check that the theme exists
+    ///
+    /// # Errors
+    /// Will return an error listing the available themes if it does not
+    pub fn validate(&self) -> std::io::Result<()> {
+        if self.mode == HighlightMode::Off || THEMES.themes.contains_key(&self.theme) {
+            return Ok(());
+        }
+
+        let themes = THEMES.themes.keys().cloned().collect::<Vec<_>>();
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "Unknown highlighting theme `{}`. Available themes are: {}",
+                self.theme,
+                themes.join(", ")
+            ),
+        ))
+    }
+
+    /// The highlighter to render code blocks with, if highlighting is on
+    #[must_use]
+    pub fn highlighter(&self) -> Option<Highlighter<'_>> {
+        let theme = THEMES.themes.get(&self.theme)?;
+        (self.mode != HighlightMode::Off).then_some(Highlighter {
+            settings: self,
+            theme,
+        })
+    }
+}
+
+/// Highlights code blocks as they are rendered
+#[derive(Debug)]
+pub struct Highlighter<'h> {
+    settings: &'h Highlighting,
+    theme: &'static Theme,
+}
+impl Highlighter<'_> {
+    /// The stylesheet needed in each page
+    #[must_use]
+    pub fn stylesheet(&self) -> Option<String> {
+        match self.settings.mode {
+            HighlightMode::Classes => css_for_theme_with_class_style(self.theme, CLASS_STYLE).ok(),
+            HighlightMode::Inline | HighlightMode::Off => None,
+        }
+    }
+
+    /// The syntax for a fence like `rust,ignore`, falling back to the default language
+    fn syntax(&self, language: Option<&str>, code: &str) -> &'static SyntaxReference {
+        let language = language
+            .and_then(|language| language.split(',').next())
+            .map(str::trim)
+            .filter(|language| !language.is_empty())
+            .unwrap_or(&self.settings.default_language);
+
+        SYNTAXES
+            .find_syntax_by_token(language)
+            .or_else(|| SYNTAXES.find_syntax_by_first_line(code))
+            .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text())
+    }
+
+    /// The highlighted HTML for the code
+    fn highlight(&self, code: &str, syntax: &SyntaxReference) -> Result<String, syntect::Error> {
+        let mut html = String::new();
+        if self.settings.mode == HighlightMode::Classes {
+            let mut generator =
+                ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+            for line in LinesWithEndings::from(code) {
+                generator.parse_html_for_line_which_includes_newline(line)?;
+            }
+            html = generator.finalize();
+        } else {
+            let background = self.theme.settings.background.unwrap_or(Color::WHITE);
+            let mut highlighter = HighlightLines::new(syntax, self.theme);
+            for line in LinesWithEndings::from(code) {
+                let regions = highlighter.highlight_line(line, &SYNTAXES)?;
+                append_highlighted_html_for_styled_line(
+                    &regions,
+                    IncludeBackground::IfDifferent(background),
+                    &mut html,
+                )?;
+            }
+        }
+        Ok(html)
+    }
+}
+impl SyntaxHighlighterAdapter for Highlighter<'_> {
+    fn write_highlighted(
+        &self,
+        output: &mut dyn Write,
+        lang: Option<&str>,
+        code: &str,
+    ) -> std::io::Result<()> {
+        let syntax = self.syntax(lang, code);
+        match self.highlight(code, syntax) {
+            Ok(html) => output.write_all(html.as_bytes()),
+            Err(_) => output.write_all(crate::chm::utilities::escape_html(code).as_bytes()),
+        }
+    }
+
+    fn write_pre_tag(
+        &self,
+        output: &mut dyn Write,
+        mut attributes: HashMap<String, String>,
+    ) -> std::io::Result<()> {
+        if self.settings.mode == HighlightMode::Classes {
+            attributes.insert("class".to_string(), "hl-code".to_string());
+        } else {
+            //
+            // The theme colours go on the block, so the page style cannot clash with them
+            let background = self
+                .theme
+                .settings
+                .background
+                .map(|Color { r, g, b, .. }| format!("background-color:#{r:02x}{g:02x}{b:02x};"))
+                .unwrap_or_default();
+            let foreground = self
+                .theme
+                .settings
+                .foreground
+                .map(|Color { r, g, b, .. }| format!("color:#{r:02x}{g:02x}{b:02x};"))
+                .unwrap_or_default();
+            attributes.insert("style".to_string(), format!("{background}{foreground}"));
+        }
+        comrak::html::write_opening_tag(output, "pre", attributes)
+    }
+
+    fn write_code_tag(
+        &self,
+        output: &mut dyn Write,
+        attributes: HashMap<String, String>,
+    ) -> std::io::Result<()> {
+        comrak::html::write_opening_tag(output, "code", attributes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chm::inputs::{InputOptions, md_load};
+    use std::path::Path;
+
+    fn render(markdown: &str, highlighting: Highlighting) -> String {
+        let options = InputOptions {
+            highlighting,
+            ..InputOptions::default()
+        };
+        let (file, _) = md_load(Path::new("src/a.md"), markdown.as_bytes(), &options).unwrap();
+        String::from_utf8(file.contents).unwrap()
+    }
+
+    fn classes() -> Highlighting {
+        Highlighting {
+            mode: HighlightMode::Classes,
+            ..Highlighting::default()
+        }
+    }
+
+    #[test]
+    fn inline_styles_or_classes() {
+        let markdown = "```rust\nlet x = 1;\n```\n";
+
+        let html = render(markdown, Highlighting::default());
+        assert!(html.contains(r#"<pre style="background-color:#ffffff;color:#323232;">"#));
+        assert!(html.contains(r#"<span style="font-weight:bold;color:#a71d5d;">let</span>"#));
+        assert!(!html.contains("hl-"));
+
+        let html = render(markdown, classes());
+        assert!(html.contains(r#"<pre class="hl-code">"#));
+        assert!(html.contains(r#"<span class="hl-storage hl-type hl-rust">let</span>"#));
+        assert!(!html.contains("<span style="));
+
+        let off = Highlighting {
+            mode: HighlightMode::Off,
+            ..Highlighting::default()
+        };
+        let html = render(markdown, off);
+        assert!(html.contains("<pre><code class=\"language-rust\">let x = 1;\n</code></pre>"));
+    }
+
+    #[test]
+    fn classes_come_with_a_stylesheet() {
+        let settings = classes();
+        let stylesheet = settings.highlighter().unwrap().stylesheet().unwrap();
+        assert!(stylesheet.contains(".hl-code {"));
+        assert!(stylesheet.contains(".hl-storage"));
+
+        let html = render("```rust\nlet x = 1;\n```\n", settings);
+        let head = &html[..html.find("<body").unwrap()];
+        assert!(head.contains(&format!("<style>\n{stylesheet}</style>")));
+
+        let settings = Highlighting::default();
+        assert_eq!(settings.highlighter().unwrap().stylesheet(), None);
+        assert!(!render("```rust\nlet x = 1;\n```\n", settings).contains(".hl-code"));
+    }
+
+    #[test]
+    fn unknown_themes_are_rejected() {
+        let mut settings = Highlighting {
+            theme: "Missing".to_string(),
+            ..Highlighting::default()
+        };
+        let error = settings.validate().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(
+            error
+                .to_string()
+                .starts_with("Unknown highlighting theme `Missing`. Available themes are: ")
+        );
+        assert!(error.to_string().contains("InspiredGitHub"));
+
+        settings.mode = HighlightMode::Off;
+        assert!(settings.validate().is_ok());
+        assert!(Highlighting::default().validate().is_ok());
+    }
+
+    #[test]
+    fn fences_fall_back_to_the_default_language_or_plain_text() {
+        let settings = Highlighting::default();
+        let highlighter = settings.highlighter().unwrap();
+        let syntax = |language| highlighter.syntax(language, "x\n").name.as_str();
+        assert_eq!(syntax(None), "Rust");
+        assert_eq!(syntax(Some("")), "Rust");
+        assert_eq!(syntax(Some("rust,ignore")), "Rust");
+        assert_eq!(syntax(Some("py")), "Python");
+        assert_eq!(syntax(Some("no-such-language")), "Plain Text");
+
+        let settings = Highlighting {
+            default_language: "python".to_string(),
+            ..Highlighting::default()
+        };
+        let highlighter = settings.highlighter().unwrap();
+        assert_eq!(highlighter.syntax(None, "x\n").name, "Python");
+
+        let html = render("```\nlet x = 1;\n```\n", classes());
+        assert!(html.contains(r#"<span class="hl-storage hl-type hl-rust">let</span>"#));
+    }
+}
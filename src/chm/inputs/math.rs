@@ -0,0 +1,35 @@
+//! Math rendering, done when the book is built since the viewer cannot run `MathJax`
+//!
+//! LaTeX is translated into typst math, typeset with the fonts bundled with typst, and written as images.\
+//! Inline math is lowered to sit on the baseline of the text around it.\
+//! Typesetting needs the `math` feature. Without it, math is left as markup
+#[cfg(feature = "math")]
+mod tex;
+
+#[cfg(feature = "math")]
+mod typeset;
+
+use super::ImageFormat;
+
+/// Math rendering settings
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MathRendering {
+    /// How math is written
+    pub format: ImageFormat,
+
+    /// Size of the math, in points. The default of 12 matches the text of the pages
+    pub font_size: f64,
+
+    /// Pixels per point in PNG images. Higher values stay sharp when the viewer is zoomed
+    pub png_scale: f32,
+}
+impl Default for MathRendering {
+    fn default() -> Self {
+        Self {
+            format: ImageFormat::default(),
+            font_size: 12.0,
+            png_scale: 2.0,
+        }
+    }
+}
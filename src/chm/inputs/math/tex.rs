@@ -0,0 +1,700 @@
+//! Translates LaTeX math into typst math, which does the typesetting
+//!
+//! Covers the commands used in books: symbols, fractions, roots, scripts, fonts, accents, text,
+//! `\left`/`\right` and the matrix, cases and aligned environments.\
+//! Anything else is reported, so the source can be shown instead
+use std::fmt::Write;
+
+/// Commands standing for a single symbol
+const SYMBOLS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("vartheta", "ϑ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("omicron", "ο"),
+    ("pi", "π"),
+    ("varpi", "ϖ"),
+    ("rho", "ρ"),
+    ("varrho", "ϱ"),
+    ("sigma", "σ"),
+    ("varsigma", "ς"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("cdot", "⋅"),
+    ("ast", "∗"),
+    ("star", "⋆"),
+    ("circ", "∘"),
+    ("bullet", "∙"),
+    ("oplus", "⊕"),
+    ("ominus", "⊖"),
+    ("otimes", "⊗"),
+    ("odot", "⊙"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("land", "∧"),
+    ("wedge", "∧"),
+    ("lor", "∨"),
+    ("vee", "∨"),
+    ("neg", "¬"),
+    ("lnot", "¬"),
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("coprod", "∐"),
+    ("int", "∫"),
+    ("iint", "∬"),
+    ("iiint", "∭"),
+    ("oint", "∮"),
+    ("bigcup", "⋃"),
+    ("bigcap", "⋂"),
+    ("bigoplus", "⨁"),
+    ("bigotimes", "⨂"),
+    ("bigvee", "⋁"),
+    ("bigwedge", "⋀"),
+    ("le", "≤"),
+    ("leq", "≤"),
+    ("ge", "≥"),
+    ("geq", "≥"),
+    ("leqslant", "⩽"),
+    ("geqslant", "⩾"),
+    ("ne", "≠"),
+    ("neq", "≠"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("cong", "≅"),
+    ("propto", "∝"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("ni", "∋"),
+    ("subset", "⊂"),
+    ("subseteq", "⊆"),
+    ("subsetneq", "⊊"),
+    ("supset", "⊃"),
+    ("supseteq", "⊇"),
+    ("mid", "∣"),
+    ("parallel", "∥"),
+    ("perp", "⟂"),
+    ("vdash", "⊢"),
+    ("models", "⊨"),
+    ("prec", "≺"),
+    ("succ", "≻"),
+    ("preceq", "⪯"),
+    ("succeq", "⪰"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("gets", "←"),
+    ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"),
+    ("implies", "⟹"),
+    ("impliedby", "⟸"),
+    ("iff", "⟺"),
+    ("mapsto", "↦"),
+    ("longrightarrow", "⟶"),
+    ("longleftarrow", "⟵"),
+    ("longmapsto", "⟼"),
+    ("uparrow", "↑"),
+    ("downarrow", "↓"),
+    ("hookrightarrow", "↪"),
+    ("rightharpoonup", "⇀"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("lbrace", "{"),
+    ("rbrace", "}"),
+    ("lvert", "|"),
+    ("rvert", "|"),
+    ("vert", "|"),
+    ("lVert", "‖"),
+    ("rVert", "‖"),
+    ("Vert", "‖"),
+    ("|", "‖"),
+    ("{", "{"),
+    ("}", "}"),
+    ("backslash", "\\"),
+    ("infty", "∞"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("nexists", "∄"),
+    ("emptyset", "∅"),
+    ("varnothing", "∅"),
+    ("angle", "∠"),
+    ("triangle", "△"),
+    ("ell", "ℓ"),
+    ("hbar", "ℏ"),
+    ("Re", "ℜ"),
+    ("Im", "ℑ"),
+    ("aleph", "ℵ"),
+    ("wp", "℘"),
+    ("prime", "′"),
+    ("top", "⊤"),
+    ("bot", "⊥"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("cdots", "⋯"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("colon", ":"),
+    ("%", "%"),
+    ("$", "$"),
+    ("#", "#"),
+    ("&", "&"),
+    ("_", "_"),
+];
+
+/// Spacing commands, and the typst spacing they become
+const SPACES: &[(&str, &str)] = &[
+    (",", "thin"),
+    (":", "med"),
+    (">", "med"),
+    (";", "thick"),
+    (" ", "space"),
+    ("quad", "quad"),
+    ("qquad", "wide"),
+    ("!", ""),
+    ("thinspace", "thin"),
+];
+
+/// Operator names typst knows
+const OPERATORS: &[&str] = &[
+    "arccos", "arcsin", "arctan", "arg", "cos", "cosh", "cot", "coth", "csc", "deg", "det", "dim",
+    "exp", "gcd", "hom", "inf", "ker", "lg", "lim", "liminf", "limsup", "ln", "log", "max", "min",
+    "Pr", "sec", "sin", "sinh", "sup", "tan", "tanh",
+];
+
+/// Commands taking one argument, and the typst functions they become, outermost first
+const FUNCTIONS: &[(&str, &[&str])] = &[
+    ("mathbb", &["bb"]),
+    ("mathbf", &["bold", "upright"]),
+    ("boldsymbol", &["bold"]),
+    ("bm", &["bold"]),
+    ("mathrm", &["upright"]),
+    ("mathit", &["italic"]),
+    ("mathcal", &["cal"]),
+    ("mathscr", &["cal"]),
+    ("mathfrak", &["frak"]),
+    ("mathsf", &["sans"]),
+    ("mathtt", &["mono"]),
+    ("hat", &["hat"]),
+    ("widehat", &["hat"]),
+    ("tilde", &["tilde"]),
+    ("widetilde", &["tilde"]),
+    ("bar", &["macron"]),
+    ("overline", &["overline"]),
+    ("underline", &["underline"]),
+    ("vec", &["arrow"]),
+    ("dot", &["dot"]),
+    ("ddot", &["dot.double"]),
+    ("acute", &["acute"]),
+    ("grave", &["grave"]),
+    ("breve", &["breve"]),
+    ("check", &["caron"]),
+    ("cancel", &["cancel"]),
+    ("sqrt", &["sqrt"]),
+];
+
+/// Commands which only size delimiters or change the style, and are ignored
+const IGNORED: &[&str] = &[
+    "big",
+    "Big",
+    "bigg",
+    "Bigg",
+    "bigl",
+    "bigr",
+    "Bigl",
+    "Bigr",
+    "biggl",
+    "biggr",
+    "Biggl",
+    "Biggr",
+    "limits",
+    "nolimits",
+    "textstyle",
+    "scriptstyle",
+    "nonumber",
+    "notag",
+];
+
+/// The value paired with a name in one of the tables above
+fn lookup<T: Copy>(table: &[(&str, T)], name: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| *value)
+}
+
+/// Translate LaTeX math into typst math
+///
+/// # Errors
+/// Will return a description of the first construct that cannot be translated
+pub fn to_typst(tex: &str) -> Result<String, String> {
+    let mut translator = Translator {
+        tokens: tokenize(tex),
+        position: 0,
+    };
+    let math = translator.expression(false)?;
+    match translator.next() {
+        None => Ok(math),
+        Some(token) => Err(format!("Unexpected `{}`", token.source())),
+    }
+}
+
+/// A piece of LaTeX source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'t> {
+    Command(&'t str),
+    Char(char),
+    Open,
+    Close,
+    Superscript,
+    Subscript,
+    Align,
+    Newline,
+}
+impl Token<'_> {
+    /// The token as it was written
+    fn source(self) -> String {
+        match self {
+            Self::Command(name) => format!("\\{name}"),
+            Self::Char(c) => c.to_string(),
+            Self::Open => "{".to_string(),
+            Self::Close => "}".to_string(),
+            Self::Superscript => "^".to_string(),
+            Self::Subscript => "_".to_string(),
+            Self::Align => "&".to_string(),
+            Self::Newline => "\\\\".to_string(),
+        }
+    }
+}
+
+/// Split LaTeX into tokens, dropping whitespace and comments
+fn tokenize(tex: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut chars = tex.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let token = match c {
+            '\\' => match chars.next() {
+                Some((_, '\\')) => Token::Newline,
+                Some((name_start, c)) if c.is_ascii_alphabetic() => {
+                    let mut end = name_start + 1;
+                    while let Some((i, c)) = chars.next_if(|(_, c)| c.is_ascii_alphabetic()) {
+                        end = i + c.len_utf8();
+                    }
+                    Token::Command(&tex[name_start..end])
+                }
+                Some((name_start, c)) => {
+                    Token::Command(&tex[name_start..name_start + c.len_utf8()])
+                }
+                None => Token::Char('\\'),
+            },
+            '%' => {
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+                continue;
+            }
+            '{' => Token::Open,
+            '}' => Token::Close,
+            '^' => Token::Superscript,
+            '_' => Token::Subscript,
+            '&' => Token::Align,
+            '~' => Token::Command(" "),
+            c if c.is_whitespace() => continue,
+            _ => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Escape a piece of text for typst math
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "/\\$#\",;()[]{}_^&@`*~!<>=:'|".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Quote text for typst
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Walks the tokens, building typst math
+struct Translator<'t> {
+    tokens: Vec<Token<'t>>,
+    position: usize,
+}
+impl<'t> Translator<'t> {
+    fn peek(&self) -> Option<Token<'t>> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<Token<'t>> {
+        let token = self.peek()?;
+        self.position += 1;
+        Some(token)
+    }
+
+    /// Atoms until the end of the group, a `\right`, or an `\end`.\
+    /// In tables, cells also end at `&` and `\\`
+    fn expression(&mut self, in_table: bool) -> Result<String, String> {
+        let mut items: Vec<String> = vec![];
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Close | Token::Command("right" | "end") => break,
+                Token::Align | Token::Newline if in_table => break,
+                Token::Align => items.push("&".to_string()),
+                Token::Newline => items.push("\\".to_string()),
+                Token::Superscript | Token::Subscript => {
+                    self.next();
+                    let script = self.argument()?;
+                    let base = items.pop().unwrap_or_else(|| "\"\"".to_string());
+                    let mark = if token == Token::Superscript {
+                        '^'
+                    } else {
+                        '_'
+                    };
+                    items.push(format!("{base}{mark}({script})"));
+                }
+                Token::Char('\'') => {
+                    self.next();
+                    let base = items.pop().unwrap_or_else(|| "\"\"".to_string());
+                    items.push(format!("{base}'"));
+                }
+                Token::Command("displaystyle") => {
+                    self.next();
+                    let rest = self.expression(in_table)?;
+                    items.push(format!("display({rest})"));
+                }
+                _ => {
+                    let atom = self.atom()?;
+                    if !atom.is_empty() {
+                        items.push(atom);
+                    }
+                }
+            }
+        }
+        Ok(items.join(" "))
+    }
+
+    /// A group in braces, or a single atom
+    fn argument(&mut self) -> Result<String, String> {
+        if self.peek() == Some(Token::Open) {
+            self.group()
+        } else {
+            self.atom()
+        }
+    }
+
+    /// The contents of a group in braces
+    fn group(&mut self) -> Result<String, String> {
+        self.expect(Token::Open)?;
+        let math = self.expression(false)?;
+        self.expect(Token::Close)?;
+        Ok(math)
+    }
+
+    /// The raw text of a group in braces, for `\text` and environment names
+    fn raw_group(&mut self) -> Result<String, String> {
+        self.expect(Token::Open)?;
+        let mut text = String::new();
+        let mut depth = 0;
+        loop {
+            match self.next() {
+                Some(Token::Close) if depth == 0 => return Ok(text),
+                Some(Token::Close) => depth -= 1,
+                Some(Token::Open) => depth += 1,
+                Some(Token::Command(" ")) => text.push(' '),
+                Some(token) => text.push_str(&token.source()),
+                None => return Err("Missing `}`".to_string()),
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: Token<'_>) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!(
+                "Expected `{}`, found `{}`",
+                expected.source(),
+                token.source()
+            )),
+            None => Err(format!("Missing `{}`", expected.source())),
+        }
+    }
+
+    /// A single symbol, command, or group
+    fn atom(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Char(c)) => Ok(escape(&c.to_string())),
+            Some(Token::Open) => {
+                self.position -= 1;
+                self.group()
+            }
+            Some(Token::Command(name)) => self.command(name),
+            Some(token) => Err(format!("Unexpected `{}`", token.source())),
+            None => Err("Missing an argument".to_string()),
+        }
+    }
+
+    /// Translate a command, after its name
+    fn command(&mut self, name: &str) -> Result<String, String> {
+        if let Some(symbol) = lookup(SYMBOLS, name) {
+            return Ok(escape(symbol));
+        }
+        if let Some(space) = lookup(SPACES, name) {
+            return Ok(space.to_string());
+        }
+        if OPERATORS.contains(&name) {
+            return Ok(name.to_string());
+        }
+        if IGNORED.contains(&name) {
+            return Ok(String::new());
+        }
+
+        match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" | "binom" => {
+                let numerator = self.argument()?;
+                let denominator = self.argument()?;
+                let function = if name == "binom" { "binom" } else { "frac" };
+                let math = format!("{function}({numerator}, {denominator})");
+                Ok(match name {
+                    "dfrac" | "cfrac" => format!("display({math})"),
+                    "tfrac" => format!("inline({math})"),
+                    _ => math,
+                })
+            }
+            "sqrt" if self.peek() == Some(Token::Char('[')) => {
+                self.next();
+                let mut index = vec![];
+                while self.peek().is_some_and(|token| token != Token::Char(']')) {
+                    index.push(self.atom()?);
+                }
+                self.expect(Token::Char(']'))?;
+                let radicand = self.argument()?;
+                Ok(format!("root({}, {radicand})", index.join(" ")))
+            }
+            "text" | "textrm" | "textnormal" | "mbox" | "hbox" => {
+                Ok(format!("upright({})", quote(&self.raw_group()?)))
+            }
+            "textbf" => Ok(format!("bold(upright({}))", quote(&self.raw_group()?))),
+            "textit" => Ok(format!("italic({})", quote(&self.raw_group()?))),
+            "operatorname" => Ok(format!("op({})", quote(&self.raw_group()?))),
+            "bmod" => Ok("mod".to_string()),
+            "pmod" => Ok(format!("\\( mod {} \\)", self.argument()?)),
+            "overbrace" | "underbrace" => {
+                let body = self.argument()?;
+                let label_mark = if name == "overbrace" {
+                    Token::Superscript
+                } else {
+                    Token::Subscript
+                };
+                if self.peek() == Some(label_mark) {
+                    self.next();
+                    return Ok(format!("{name}({body}, {})", self.argument()?));
+                }
+                Ok(format!("{name}({body})"))
+            }
+            "not" => Ok(format!("{}\u{338}", self.atom()?)),
+            "left" => self.left_right(),
+            "begin" => self.environment(),
+            _ => match lookup(FUNCTIONS, name) {
+                Some(functions) => {
+                    let argument = self.argument()?;
+                    Ok(functions
+                        .iter()
+                        .rev()
+                        .fold(argument, |math, function| format!("{function}({math})")))
+                }
+                None => Err(format!("Unsupported command `\\{name}`")),
+            },
+        }
+    }
+
+    /// A delimiter after `\left`, `\right` or a sizing command. `.` is no delimiter
+    fn delimiter(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Char('.')) => Ok(String::new()),
+            Some(Token::Char(c)) => Ok(escape(&c.to_string())),
+            Some(Token::Command(name)) => self.command(name),
+            _ => Err("Missing a delimiter".to_string()),
+        }
+    }
+
+    /// Delimiters sized to their contents, after `\left`
+    fn left_right(&mut self) -> Result<String, String> {
+        let left = self.delimiter()?;
+        let body = self.expression(false)?;
+        self.expect(Token::Command("right"))?;
+        let right = self.delimiter()?;
+        Ok(format!("lr({left} {body} {right})"))
+    }
+
+    /// An environment, after `\begin`
+    fn environment(&mut self) -> Result<String, String> {
+        let name = self.raw_group()?;
+        if name == "array" {
+            self.raw_group()?;
+        }
+
+        let mut rows = vec![];
+        loop {
+            let mut cells = vec![];
+            loop {
+                cells.push(self.expression(true)?);
+                if self.peek() != Some(Token::Align) {
+                    break;
+                }
+                self.next();
+            }
+            rows.push(cells);
+            if self.peek() != Some(Token::Newline) {
+                break;
+            }
+            self.next();
+        }
+        self.expect(Token::Command("end"))?;
+        let end = self.raw_group()?;
+        if end != name {
+            return Err(format!("`\\begin{{{name}}}` ended by `\\end{{{end}}}`"));
+        }
+
+        //
+        // A trailing `\\` leaves an empty row
+        if rows.len() > 1
+            && rows
+                .last()
+                .is_some_and(|row| row.iter().all(String::is_empty))
+        {
+            rows.pop();
+        }
+
+        let delimiter = match name.trim_end_matches('*') {
+            "matrix" | "smallmatrix" | "array" => "#none",
+            "pmatrix" => "\"(\"",
+            "bmatrix" => "\"[\"",
+            "Bmatrix" => "\"{\"",
+            "vmatrix" => "\"|\"",
+            "Vmatrix" => "\"||\"",
+            "cases" | "dcases" => {
+                let rows = rows
+                    .iter()
+                    .map(|row| row.join(" & quad "))
+                    .collect::<Vec<_>>();
+                return Ok(format!("cases({})", rows.join(", ")));
+            }
+            "aligned" | "align" | "alignat" | "alignedat" | "split" | "gather" | "gathered"
+            | "eqnarray" | "multline" => {
+                let rows = rows.iter().map(|row| row.join(" & ")).collect::<Vec<_>>();
+                return Ok(rows.join(" \\ "));
+            }
+            _ => return Err(format!("Unsupported environment `{name}`")),
+        };
+
+        let mut matrix = format!("mat(delim: {delimiter}");
+        for (i, row) in rows.iter().enumerate() {
+            let separator = if i == 0 { ", " } else { "; " };
+            write!(matrix, "{separator}{}", row.join(", ")).ok();
+        }
+        matrix.push(')');
+        Ok(matrix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_typst;
+
+    #[test]
+    fn fractions() {
+        assert_eq!(to_typst(r"\frac{a}{b}").unwrap(), "frac(a, b)");
+        assert_eq!(to_typst(r"\dfrac 1 2").unwrap(), "display(frac(1, 2))");
+        assert_eq!(to_typst(r"\binom{n}{k}").unwrap(), "binom(n, k)");
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(to_typst("x^2").unwrap(), "x^(2)");
+        assert_eq!(to_typst("x_{i}^{2}").unwrap(), "x_(i)^(2)");
+        assert_eq!(to_typst(r"\sum_{i=1}^n i").unwrap(), "∑_(i \\= 1)^(n) i");
+        assert_eq!(to_typst("f'").unwrap(), "f'");
+    }
+
+    #[test]
+    fn fonts() {
+        assert_eq!(to_typst(r"\mathbf{v}").unwrap(), "bold(upright(v))");
+        assert_eq!(to_typst(r"\mathbb R").unwrap(), "bb(R)");
+        assert_eq!(
+            to_typst(r"\vec{\mathbf{x}}").unwrap(),
+            "arrow(bold(upright(x)))"
+        );
+    }
+
+    #[test]
+    fn delimiters() {
+        assert_eq!(to_typst(r"\left( x \right)").unwrap(), r"lr(\( x \))");
+        assert_eq!(
+            to_typst(r"\left. \frac{a}{b} \right|").unwrap(),
+            r"lr( frac(a, b) \|)"
+        );
+        assert!(to_typst(r"\left( x").is_err());
+    }
+
+    #[test]
+    fn unknown_commands() {
+        assert_eq!(
+            to_typst(r"\foo{x}").unwrap_err(),
+            r"Unsupported command `\foo`"
+        );
+        assert_eq!(
+            to_typst(r"\begin{tikzpicture}\end{tikzpicture}").unwrap_err(),
+            "Unsupported environment `tikzpicture`"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! Typesets math with typst, into SVG or PNG images
+use super::{ImageFormat, MathRendering, tex};
+use std::sync::LazyLock;
+use typst::{
+    Library, LibraryExt, World,
+    diag::{FileError, FileResult},
+    foundations::{Bytes, Datetime, Duration},
+    layout::{Abs, Frame, FrameItem},
+    syntax::{FileId, Source},
+    text::{Font, FontBook},
+    utils::LazyHash,
+};
+use typst_layout::PagedDocument;
+
+static LIBRARY: LazyLock<LazyHash<Library>> = LazyLock::new(|| LazyHash::new(Library::default()));
+static FONTS: LazyLock<Vec<Font>> = LazyLock::new(|| {
+    typst_assets::fonts()
+        .flat_map(|data| Font::iter(Bytes::new(data)))
+        .collect()
+});
+static BOOK: LazyLock<LazyHash<FontBook>> =
+    LazyLock::new(|| LazyHash::new(FontBook::from_fonts(FONTS.iter())));
+
+impl MathRendering {
+    /// The typst source for some LaTeX math
+    ///
+    /// # Errors
+    /// Will return a description of the LaTeX that cannot be translated
+    pub fn source(&self, tex: &str, display: bool) -> Result<String, String> {
+        let math = tex::to_typst(tex)?;
+
+        //
+        // Inline math starts with an empty box, whose bottom is on the baseline.
+        // A space after it would show
+        let math = if display {
+            math
+        } else {
+            format!("inline(#box(width: 0pt, text(size: 0.1pt)[x]){math})")
+        };
+        Ok(format!(
+            "#set page(width: auto, height: auto, margin: 0pt, fill: none)\n\
+             #set text(size: {}pt)\n\
+             $ {math} $",
+            self.font_size
+        ))
+    }
+
+    /// Typeset the typst source from [`Self::source`] into an image
+    ///
+    /// # Errors
+    /// Will return the typst errors if the math cannot be typeset, or an error if math rendering is turned off
+    pub fn render(&self, source: &str, display: bool) -> Result<RenderedMath, String> {
+        if self.format == ImageFormat::Off {
+            return Err("Math rendering is turned off".to_string());
+        }
+        let world = MathWorld {
+            source: Source::detached(source),
+        };
+        let document = typst::compile::<PagedDocument>(&world)
+            .output
+            .map_err(|errors| {
+                errors
+                    .iter()
+                    .map(|error| error.message.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })?;
+        let mut page = document
+            .pages()
+            .first()
+            .cloned()
+            .ok_or_else(|| "Nothing to render".to_string())?;
+
+        let baseline = if display {
+            page.frame.height()
+        } else {
+            let (frame, baseline) = take_baseline_marker(&page.frame);
+            page.frame = frame;
+            baseline
+        };
+
+        let image = match self.format {
+            ImageFormat::Png => {
+                let options = typst_render::RenderOptions {
+                    pixel_per_pt: f64::from(self.png_scale).into(),
+                    render_bleed: false,
+                };
+                typst_render::render(&page, &options)
+                    .encode_png()
+                    .map_err(|e| e.to_string())?
+            }
+            ImageFormat::Svg => {
+                typst_svg::svg(&page, &typst_svg::SvgOptions::default()).into_bytes()
+            }
+            ImageFormat::Off => unreachable!("math is not rendered when turned off"),
+        };
+        Ok(RenderedMath {
+            image,
+            width: page.frame.width().to_pt(),
+            height: page.frame.height().to_pt(),
+            depth: (page.frame.height() - baseline).to_pt(),
+        })
+    }
+}
+
+/// Math typeset into an image
+#[derive(Debug, Clone)]
+pub struct RenderedMath {
+    /// The image file
+    pub image: Vec<u8>,
+
+    /// Width of the image, in points
+    pub width: f64,
+
+    /// Height of the image, in points
+    pub height: f64,
+
+    /// Distance from the baseline to the bottom of the image, in points
+    pub depth: f64,
+}
+
+/// Removes the empty box from the start of inline math, and returns the frame with its baseline
+fn take_baseline_marker(frame: &Frame) -> (Frame, Abs) {
+    find_baseline_marker(frame).unwrap_or_else(|| (frame.clone(), frame.height()))
+}
+
+/// Looks for the empty box, which can be inside the groups typst wraps the math in
+fn find_baseline_marker(frame: &Frame) -> Option<(Frame, Abs)> {
+    let mut unmarked = Frame::new(frame.size(), frame.kind());
+    let mut baseline = None;
+    for (position, item) in frame.items() {
+        if baseline.is_none()
+            && let FrameItem::Group(group) = item
+        {
+            if group.frame.width() == Abs::zero() {
+                baseline = Some(position.y + group.frame.height());
+                continue;
+            }
+            if let Some((inner, inner_baseline)) = find_baseline_marker(&group.frame) {
+                baseline = Some(position.y + inner_baseline);
+                let mut group = group.clone();
+                group.frame = inner;
+                unmarked.push(*position, FrameItem::Group(group));
+                continue;
+            }
+        }
+        unmarked.push(*position, item.clone());
+    }
+    baseline.map(|baseline| (unmarked, baseline))
+}
+
+/// The files typst can see while typesetting: the math, and the bundled fonts
+struct MathWorld {
+    source: Source,
+}
+impl World for MathWorld {
+    fn library(&self) -> &LazyHash<Library> {
+        &LIBRARY
+    }
+
+    fn book(&self) -> &LazyHash<FontBook> {
+        &BOOK
+    }
+
+    fn main(&self) -> FileId {
+        self.source.id()
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        if id == self.source.id() {
+            Ok(self.source.clone())
+        } else {
+            Err(FileError::AccessDenied)
+        }
+    }
+
+    fn file(&self, _id: FileId) -> FileResult<Bytes> {
+        Err(FileError::AccessDenied)
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        FONTS.get(index).cloned()
+    }
+
+    fn today(&self, _offset: Option<Duration>) -> Option<Datetime> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_to_render_when_turned_off() {
+        let options = MathRendering {
+            format: ImageFormat::Off,
+            ..MathRendering::default()
+        };
+        let source = options.source("x^2", false).unwrap();
+        assert_eq!(
+            options.render(&source, false).unwrap_err(),
+            "Math rendering is turned off"
+        );
+    }
+}
@@ -1,15 +1,64 @@
-use crate::chm::contents::File;
-use comrak::{Arena, ComrakOptions, ExtensionOptions, nodes::NodeValue};
+use crate::chm::{
+    contents::{File, FileMetadata},
+    inputs::InputOptions,
+    popups::ChmTextPopup,
+};
+use comrak::{
+    Arena, ComrakOptions, ExtensionOptions, Plugins,
+    adapters::SyntaxHighlighterAdapter,
+    nodes::{AstNode, NodeValue},
+};
 use std::{
     io::BufWriter,
     path::{Path, PathBuf},
 };
 
+mod admonitions;
+use admonitions::{expand_admonitions, expand_alerts};
+
+mod alinks;
+
+mod assets;
+use assets::collect_assets;
+
+use alinks::extract_alinks;
+
+mod code;
+use code::extract_code_identifiers;
+
+mod codeblocks;
+use codeblocks::prepare_code_blocks;
+
+#[cfg(feature = "diagrams")]
+mod diagrams;
+#[cfg(feature = "diagrams")]
+use diagrams::render_diagrams;
+
+mod directives;
+
+mod headings;
+use headings::assign_heading_ids;
+
+mod links;
+use links::{collect_anchors, resolve_links};
+
+mod markers;
+use markers::extract_index_markers;
+
+#[cfg(feature = "math")]
+mod math;
+#[cfg(feature = "math")]
+use math::render_math;
+
 /// Loads a mardown file, rendering it as HTML
 ///
 /// # Errors
 /// Can return an error if the source cannot be read
-pub fn load(path: &Path, contents: &[u8]) -> std::io::Result<(File, Vec<PathBuf>)> {
+pub fn load(
+    path: &Path,
+    contents: &[u8],
+    input_options: &InputOptions,
+) -> std::io::Result<(File, Vec<PathBuf>)> {
     // Convert the contents to a string
     let contents = String::from_utf8_lossy(contents).to_string();
 
@@ -50,63 +99,167 @@ pub fn load(path: &Path, contents: &[u8]) -> std::io::Result<(File, Vec<PathBuf>
     // Parse the contents
     let arena = Arena::new();
     let root = comrak::parse_document(&arena, &contents, &options);
+    expand_admonitions(&arena, root, &options);
+    expand_alerts(&arena, root);
 
     //
-    // We need to scan the tree and find all the images
-    let mut dependencies = vec![];
-    for node in root.descendants() {
-        if let NodeValue::Image(ref image) = node.data.borrow().value {
-            if image.url.starts_with("http") {
-                continue;
-            }
+    // Pull out popup text, index markers and associative links before anything else sees them
+    let popups = extract_popups(root)?;
+    let headings = assign_heading_ids(&arena, root);
+    let index_markers = extract_index_markers(&arena, root, &headings);
+    let headings = headings
+        .into_iter()
+        .map(|heading| heading.heading)
+        .collect();
+    let alinks = extract_alinks(&arena, root);
 
-            let mut img_path = Path::new(&image.url).to_path_buf();
+    //
+    // Hide the boilerplate lines in examples, like the HTML book does.
+    // Identifiers are only collected from the lines left, which readers can see
+    prepare_code_blocks(&arena, root, &input_options.hidden_lines);
+    let code_identifiers = if input_options.code_identifiers {
+        extract_code_identifiers(&arena, root)
+    } else {
+        vec![]
+    };
 
-            //
-            // Possibly relative, set to parent of the current file
-            if img_path.is_relative() {
-                if let Some(parent) = path.parent() {
-                    img_path = parent.join(&img_path);
-                }
-            }
+    if let Some(format) = &input_options.first_heading_format {
+        format_first_heading(&arena, root, format);
+    }
 
-            dependencies.push(img_path);
-        }
+    //
+    // We need to scan the tree and find all the images, and other assets in raw HTML
+    let dependencies = collect_assets(root, path, input_options);
 
-        //
-        // We also need to set the extension of all links pointing to relative .md files to .html
-        if let NodeValue::Link(ref mut link) = node.data.borrow_mut().value {
-            if !link.url.starts_with("http") {
-                let path = Path::new(&link.url);
-                if path.is_relative() {
-                    link.url = path.with_extension("html").to_string_lossy().to_string();
-                }
-            }
-        }
+    //
+    // Math and diagrams are rendered into images, which are written with the topic
+    #[cfg_attr(not(any(feature = "math", feature = "diagrams")), allow(unused_mut))]
+    let mut generated = vec![];
+    #[cfg(feature = "math")]
+    if input_options.math.format != crate::chm::inputs::ImageFormat::Off {
+        generated.extend(render_math(&arena, root, path, &input_options.math));
     }
+    #[cfg(feature = "diagrams")]
+    generated.extend(render_diagrams(&arena, root, path, &input_options.diagrams));
+
+    //
+    // Links to other chapters need to point at their topics
+    let anchor_links = resolve_links(root, path, input_options.chapters.as_deref());
 
     //
     // Get the html representation of the file
+    let highlighter = input_options.highlighting.highlighter();
+    let mut plugins = Plugins::default();
+    plugins.render.codefence_syntax_highlighter = highlighter
+        .as_ref()
+        .map(|highlighter| highlighter as &dyn SyntaxHighlighterAdapter);
+
     let mut bw = BufWriter::new(Vec::new());
-    comrak::format_html(root, &options, &mut bw)?;
+    comrak::format_html_with_plugins(root, &options, &mut bw, &plugins)?;
     let html = String::from_utf8(bw.into_inner().unwrap_or_default()).unwrap_or_default();
-    let html = HTML_TEMPLATE.replace("%BODY%", &html);
+    let anchors = collect_anchors(&html);
+
+    let head = highlighter
+        .and_then(|highlighter| highlighter.stylesheet())
+        .map(|css| format!("<style>\n{css}</style>"))
+        .unwrap_or_default();
+    let html = HTML_TEMPLATE
+        .replace("%HEAD%", &head)
+        .replace("%BODY%", &html);
 
     //
     // Return the html contents
     let own_file = File {
         path: path.with_extension("html"),
         contents: html.as_bytes().to_vec(),
+        metadata: FileMetadata {
+            popups,
+            index_markers,
+            headings,
+            code_identifiers,
+            alinks,
+            anchors,
+            anchor_links,
+            generated,
+        },
     };
     Ok((own_file, dependencies))
 }
 
+/// Rewrites the first heading in the document using a format like `{number} {title}`
+fn format_first_heading<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>, format: &str) {
+    let Some(heading) = root
+        .descendants()
+        .find(|node| matches!(node.data.borrow().value, NodeValue::Heading(_)))
+    else {
+        return;
+    };
+
+    let (prefix, suffix) = format.split_once("{title}").unwrap_or((format, ""));
+    if !prefix.is_empty() {
+        //
+        // Keep the heading anchor in front
+        let node = arena.alloc(NodeValue::Text(prefix.to_string()).into());
+        match heading.first_child() {
+            Some(anchor) if matches!(anchor.data.borrow().value, NodeValue::HtmlInline(_)) => {
+                anchor.insert_after(node);
+            }
+            _ => heading.prepend(node),
+        }
+    }
+    if !suffix.is_empty() {
+        let node = arena.alloc(NodeValue::Text(suffix.to_string()).into());
+        heading.append(node);
+    }
+}
+
+/// Removes all the ```` ```popup ID [context] ```` blocks from the document, returning them as text popups
+fn extract_popups<'a>(root: &'a AstNode<'a>) -> std::io::Result<Vec<ChmTextPopup>> {
+    let mut popups = vec![];
+    let mut popup_nodes = vec![];
+    for node in root.descendants() {
+        let NodeValue::CodeBlock(ref block) = node.data.borrow().value else {
+            continue;
+        };
+
+        let mut info = block.info.split_whitespace();
+        if info.next() != Some("popup") {
+            continue;
+        }
+
+        let Some(id) = info.next() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Popup blocks need an ID, like ```popup IDH_SAVE",
+            ));
+        };
+        let context = match info.next() {
+            Some(context) => Some(context.parse().map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Invalid context number `{context}` for popup `{id}`"),
+                )
+            })?),
+            None => None,
+        };
+
+        popups.push(ChmTextPopup::new(&id, &block.literal, context)?);
+        popup_nodes.push(node);
+    }
+
+    for node in popup_nodes {
+        node.detach();
+    }
+
+    Ok(popups)
+}
+
 const HTML_TEMPLATE: &str = r#"
 <!DOCTYPE html>
 <html>
 <head>
-    <meta name="GENERATOR" content="@rscarson® mdbook-chm">
     <meta http-equiv="X-UA-Compatible" content="IE=edge" />
+    <meta name="GENERATOR" content="@rscarson® mdbook-chm">
     <meta charset="utf-8">    
     <style>
         body {
@@ -132,6 +285,90 @@ const HTML_TEMPLATE: &str = r#"
             padding: 1em;
             overflow-x: auto;
         }
+        pre code {
+            background: transparent;
+            padding: 0;
+        }
+        .math-display {
+            display: block;
+            margin: 1em 0;
+            text-align: center;
+        }
+        .diagram {
+            margin: 1em 0;
+            text-align: center;
+        }
+        .collapsible {
+            margin: 0 0 1em 0;
+        }
+        .collapsible-toggle {
+            position: absolute;
+            left: -9999px;
+        }
+        .collapsible label {
+            color: #0645ad;
+            cursor: pointer;
+        }
+        .collapsible-toggle:not(:checked) ~ .collapsible-body {
+            display: none;
+        }
+        .markdown-alert {
+            margin: 1em 0;
+            padding: 0.5em 1em;
+            border-left: 4px solid #0969da;
+            background-color: #eef5ff;
+        }
+        .markdown-alert p {
+            margin: 0.5em 0;
+        }
+        .markdown-alert .markdown-alert-title {
+            display: block;
+            margin: 0 0 0.5em 0;
+            font-weight: 600;
+        }
+        .markdown-alert-icon {
+            margin-right: 0.4em;
+            font-family: "Segoe UI Symbol", "Segoe UI", sans-serif;
+        }
+        .markdown-alert-note .markdown-alert-title {
+            color: #0969da;
+        }
+        .markdown-alert-tip {
+            border-color: #1a7f37;
+            background-color: #eefbf1;
+        }
+        .markdown-alert-tip .markdown-alert-title {
+            color: #1a7f37;
+        }
+        .markdown-alert-important {
+            border-color: #8250df;
+            background-color: #f5f0ff;
+        }
+        .markdown-alert-important .markdown-alert-title {
+            color: #8250df;
+        }
+        .markdown-alert-warning {
+            border-color: #9a6700;
+            background-color: #fff8e5;
+        }
+        .markdown-alert-warning .markdown-alert-title {
+            color: #9a6700;
+        }
+        .markdown-alert-caution {
+            border-color: #cf222e;
+            background-color: #ffeef0;
+        }
+        .markdown-alert-caution .markdown-alert-title {
+            color: #cf222e;
+        }
+        .code-label {
+            margin-bottom: -0.5em;
+            padding: 2px 8px;
+            font-size: 12px;
+            color: #fff;
+            background-color: #c33;
+            display: inline-block;
+        }
         a {
             color: #0645ad;
             text-decoration: none;
@@ -162,9 +399,50 @@ const HTML_TEMPLATE: &str = r#"
             background-color: #f9f9f9;
         }
     </style>
+    %HEAD%
 </head>
 <body>
     %BODY%
 </body>
 </html>
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn popups(markdown: &str) -> std::io::Result<Vec<ChmTextPopup>> {
+        let arena = Arena::new();
+        let root = comrak::parse_document(&arena, markdown, &ComrakOptions::default());
+        extract_popups(root)
+    }
+
+    #[test]
+    fn extracts_popups() {
+        let found =
+            popups("Text\n\n```popup IDH_SAVE 1002\nSaves\n```\n\n```popup IDH_OPEN\nOpens\n```\n")
+                .unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            (
+                found[0].id.as_str(),
+                found[0].context,
+                found[0].text.as_str()
+            ),
+            ("IDH_SAVE", Some(1002), "Saves\n")
+        );
+        assert_eq!((found[1].id.as_str(), found[1].context), ("IDH_OPEN", None));
+    }
+
+    #[test]
+    fn rejects_malformed_popups() {
+        let error = popups("```popup\nSaves\n```\n").unwrap_err();
+        assert!(error.to_string().contains("need an ID"));
+
+        let error = popups("```popup IDH_SAVE first\nSaves\n```\n").unwrap_err();
+        assert!(error.to_string().contains("Invalid context number `first`"));
+
+        let error = popups("```popup 1DH_SAVE\nSaves\n```\n").unwrap_err();
+        assert!(error.to_string().contains("Invalid popup ID"));
+    }
+}
@@ -0,0 +1,305 @@
+//! Expands `admonish` code blocks, from mdbook-admonish, and GitHub alerts into callouts
+//!
+//! Both share the markup of the alerts comrak renders, so they are styled the same way.
+//! Icons are characters in the title rather than generated content, which the IE7 mode of the viewer cannot show.\
+//! Fences look like ```` ```admonish warning "Title" ```` or ```` ```admonish tip title="Title" collapsible=true ````,
+//! and collapsible callouts open without scripts. Where `:checked` is unsupported, as in IE7 mode, they stay open
+use crate::chm::utilities::escape_html;
+use comrak::{
+    Arena, ComrakOptions,
+    nodes::{AstNode, NodeHtmlBlock, NodeValue},
+};
+
+/// Icon in the title of each kind of alert
+const ICONS: &[(&str, &str)] = &[
+    ("note", "&#x2139;"),
+    ("tip", "&#x2714;"),
+    ("important", "&#x2757;"),
+    ("warning", "&#x26A0;"),
+    ("caution", "&#x26D4;"),
+];
+
+/// Admonition types, and the kind of alert they are styled as
+const KINDS: &[(&str, &str)] = &[
+    ("note", "note"),
+    ("abstract", "note"),
+    ("summary", "note"),
+    ("tldr", "note"),
+    ("info", "note"),
+    ("todo", "note"),
+    ("example", "note"),
+    ("quote", "note"),
+    ("cite", "note"),
+    ("question", "note"),
+    ("help", "note"),
+    ("faq", "note"),
+    ("tip", "tip"),
+    ("hint", "tip"),
+    ("success", "tip"),
+    ("check", "tip"),
+    ("done", "tip"),
+    ("important", "important"),
+    ("warning", "warning"),
+    ("caution", "warning"),
+    ("attention", "warning"),
+    ("failure", "caution"),
+    ("fail", "caution"),
+    ("missing", "caution"),
+    ("danger", "caution"),
+    ("error", "caution"),
+    ("bug", "caution"),
+];
+
+/// An admonition, from the info string of its fence
+struct Admonition {
+    /// The admonition type, like `warning`
+    directive: String,
+
+    /// Title of the callout. Empty for no title
+    title: String,
+
+    /// True if the body starts hidden
+    collapsible: bool,
+}
+
+/// Replaces the `admonish` code blocks in the document with callouts holding their rendered contents
+pub fn expand_admonitions<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    options: &ComrakOptions,
+) {
+    //
+    // Callouts can hold other callouts, so each pass expands the first one left
+    let mut number = 0;
+    while let Some((node, info, body)) = root.descendants().find_map(|node| {
+        let data = node.data.borrow();
+        let NodeValue::CodeBlock(block) = &data.value else {
+            return None;
+        };
+        let info = block.info.trim().strip_prefix("admonish")?;
+        (info.is_empty() || info.starts_with(char::is_whitespace))
+            .then(|| (node, info.to_string(), block.literal.clone()))
+    }) {
+        number += 1;
+        let admonition = parse_info(&info);
+
+        //
+        // Only the known types reach the markup
+        let (directive, kind) = KINDS
+            .iter()
+            .find(|(directive, _)| *directive == admonition.directive)
+            .copied()
+            .unwrap_or_else(|| {
+                eprintln!(
+                    "Warning: Unknown admonition type `{}`, shown as a note",
+                    admonition.directive
+                );
+                ("note", "note")
+            });
+
+        let classes = format!("markdown-alert markdown-alert-{kind} admonish-{directive}");
+        let title = if admonition.title.is_empty() {
+            String::new()
+        } else {
+            title_html(kind, &escape_html(&admonition.title))
+        };
+        let (open, close) = if admonition.collapsible {
+            let id = format!("admonition-{number}");
+            (
+                format!(
+                    "<div class=\"{classes} collapsible\">\
+                     <input type=\"checkbox\" class=\"collapsible-toggle\" id=\"{id}\">\
+                     <label for=\"{id}\" class=\"markdown-alert-title\">{title}</label>\
+                     <div class=\"collapsible-body\">\n"
+                ),
+                "</div></div>\n",
+            )
+        } else if title.is_empty() {
+            (format!("<div class=\"{classes}\">\n"), "</div>\n")
+        } else {
+            (
+                format!("<div class=\"{classes}\"><p class=\"markdown-alert-title\">{title}</p>\n"),
+                "</div>\n",
+            )
+        };
+
+        let contents = comrak::parse_document(arena, &body, options);
+        replace_with_callout(arena, node, contents, open, close);
+    }
+}
+
+/// Replaces the GitHub alerts in the document with callouts, which have an icon in their title
+pub fn expand_alerts<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) {
+    let alerts = root
+        .descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::Alert(_)))
+        .collect::<Vec<_>>();
+    for node in alerts {
+        let open = {
+            let NodeValue::Alert(alert) = &node.data.borrow().value else {
+                continue;
+            };
+            let class = alert.alert_type.css_class();
+            let kind = class.trim_start_matches("markdown-alert-");
+            let title = match &alert.title {
+                Some(title) => escape_html(title),
+                None => alert.alert_type.default_title(),
+            };
+            format!(
+                "<div class=\"markdown-alert {class}\"><p class=\"markdown-alert-title\">{}</p>\n",
+                title_html(kind, &title)
+            )
+        };
+        replace_with_callout(arena, node, node, open, "</div>\n");
+    }
+}
+
+/// The markup of a callout title: its icon, and the escaped title
+fn title_html(kind: &str, title: &str) -> String {
+    let icon = ICONS
+        .iter()
+        .find(|(icon_kind, _)| *icon_kind == kind)
+        .map_or("&#x2139;", |(_, icon)| *icon);
+    format!("<span class=\"markdown-alert-icon\">{icon}</span>{title}")
+}
+
+/// Puts the children of `contents` between the opening and closing markup, in place of `node`
+fn replace_with_callout<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    node: &'a AstNode<'a>,
+    contents: &'a AstNode<'a>,
+    open: String,
+    close: &str,
+) {
+    let html = |literal: String| -> &'a AstNode<'a> {
+        arena.alloc(
+            NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 6,
+                literal,
+            })
+            .into(),
+        )
+    };
+    node.insert_before(html(open));
+    for child in contents.children().collect::<Vec<_>>() {
+        node.insert_before(child);
+    }
+    node.insert_before(html(close.to_string()));
+    node.detach();
+}
+
+/// Reads the words after `admonish`: the type, a quoted title, and `key=value` settings
+fn parse_info(info: &str) -> Admonition {
+    let mut directive = None;
+    let mut title = None;
+    let mut collapsible = false;
+
+    let mut rest = info.trim_start();
+    while !rest.is_empty() {
+        let (word, remainder) = split_word(rest);
+        rest = remainder.trim_start();
+
+        if let Some(quoted) = unquote(&word) {
+            title = Some(quoted);
+        } else if let Some((key, value)) = word.split_once('=') {
+            let value = unquote(value).unwrap_or_else(|| value.to_string());
+            match key {
+                "title" => title = Some(value),
+                "collapsible" => collapsible = value == "true",
+                _ => {}
+            }
+        } else if directive.is_none() {
+            directive = Some(word);
+        }
+    }
+
+    let directive = directive.unwrap_or_else(|| "note".to_string());
+    let mut chars = directive.chars();
+    let default_title = chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default();
+
+    //
+    // A collapsed callout needs a title to open it by
+    let title = match title {
+        Some(title) if !(collapsible && title.is_empty()) => title,
+        _ => default_title,
+    };
+    Admonition {
+        directive,
+        title,
+        collapsible,
+    }
+}
+
+/// The next word, where quoted parts can hold spaces, and the text after it
+fn split_word(text: &str) -> (String, &str) {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => return (text[..i].to_string(), &text[i..]),
+            _ => {}
+        }
+    }
+    (text.to_string(), "")
+}
+
+/// The text inside double quotes, with `\"` escapes resolved
+fn unquote(text: &str) -> Option<String> {
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    Some(inner.replace("\\\"", "\"").replace("\\\\", "\\"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(markdown: &str) -> String {
+        let mut options = ComrakOptions::default();
+        options.render.unsafe_ = true;
+        options.extension.alerts = true;
+        let arena = Arena::new();
+        let root = comrak::parse_document(&arena, markdown, &options);
+        expand_admonitions(&arena, root, &options);
+        expand_alerts(&arena, root);
+        let mut html = vec![];
+        comrak::format_html(root, &options, &mut html).unwrap();
+        String::from_utf8(html).unwrap()
+    }
+
+    #[test]
+    fn unknown_types_do_not_reach_the_markup() {
+        let html = render("```admonish x\" onclick=\"alert(1)\nBody\n```\n");
+        assert!(
+            html.starts_with("<div class=\"markdown-alert markdown-alert-note admonish-note\">")
+        );
+        assert!(!html.contains("onclick=\""));
+
+        let html = render("```admonish danger \"A <b>title</b>\"\nBody\n```\n");
+        assert!(html.contains("markdown-alert-caution admonish-danger"));
+        assert!(html.contains("A &lt;b&gt;title&lt;/b&gt;"));
+    }
+
+    #[test]
+    fn titles_hold_their_icon() {
+        let html = render("> [!WARNING]\n> Body\n");
+        assert!(html.starts_with(
+            "<div class=\"markdown-alert markdown-alert-warning\"><p class=\"markdown-alert-title\">\
+             <span class=\"markdown-alert-icon\">&#x26A0;</span>Warning</p>"
+        ));
+        assert!(html.contains("<p>Body</p>"));
+
+        let html = render("> [!TIP] Use <this>\n> Body\n");
+        assert!(html.contains("&#x2714;</span>Use &lt;this&gt;</p>"));
+
+        let html = render("```admonish bug collapsible=true\nBody\n```\n");
+        assert!(html.contains(
+            "class=\"markdown-alert-title\"><span class=\"markdown-alert-icon\">&#x26D4;</span>Bug</label>"
+        ));
+    }
+}
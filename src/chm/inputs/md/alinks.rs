@@ -0,0 +1,132 @@
+//! Associative link directives
+//!
+//! `{{#alink Group; Other group}}` adds the topic to `ALink` groups, and is removed from the text.\
+//! `{{#related}}` places the related topics of the topic, or of the listed groups with `{{#related Group}}`.\
+//! Topics in a group without a `{{#related}}` directive get their related topics at the end
+use super::directives::{is_blank, replace_in_text, split_list};
+use crate::chm::alinks::{alink_names, related_placeholder};
+use comrak::{
+    Arena,
+    nodes::{AstNode, NodeHtmlBlock, NodeValue},
+};
+use regex::Regex;
+use std::sync::LazyLock;
+
+static ALINK_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{#alink\s+([^}]*)\}\}").unwrap());
+
+static RELATED_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{#related(?:\s+([^}]*))?\}\}").unwrap());
+
+/// Collects the `ALink` groups of the document, and places its related topics
+pub fn extract_alinks<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) -> Vec<String> {
+    let mut groups: Vec<String> = vec![];
+    replace_in_text(arena, root, &ALINK_REGEX, |captures| {
+        for group in split_list(&captures[1]) {
+            if !groups.iter().any(|known| known == group) {
+                groups.push(group.to_string());
+            }
+        }
+        None
+    });
+
+    let mut placeholders = vec![];
+    replace_in_text(arena, root, &RELATED_REGEX, |captures| {
+        let related: Vec<String> = captures
+            .get(1)
+            .map(|groups| split_list(groups.as_str()).map(str::to_string).collect())
+            .unwrap_or_default();
+        let node: &'a AstNode<'a> =
+            arena.alloc(NodeValue::HtmlInline(related_placeholder(&related)).into());
+        placeholders.push(node);
+        Some(node)
+    });
+
+    //
+    // A directive on its own line becomes a block, instead of sitting in a paragraph
+    for node in placeholders.iter().copied() {
+        let Some(parent) = node.parent() else {
+            continue;
+        };
+        let alone = parent
+            .children()
+            .all(|child| child.same_node(node) || is_blank(child));
+        if alone && matches!(parent.data.borrow().value, NodeValue::Paragraph) {
+            let literal = match &node.data.borrow().value {
+                NodeValue::HtmlInline(literal) => format!("{literal}\n"),
+                _ => continue,
+            };
+            parent.insert_before(html_block(arena, literal));
+            parent.detach();
+        }
+    }
+
+    if groups.is_empty() {
+        return groups;
+    }
+
+    if placeholders.is_empty() {
+        root.append(html_block(arena, format!("{}\n", related_placeholder(&[]))));
+    }
+    root.prepend(html_block(arena, format!("{}\n", alink_names(&groups))));
+    groups
+}
+
+/// A raw HTML block
+fn html_block<'a>(arena: &'a Arena<AstNode<'a>>, literal: String) -> &'a AstNode<'a> {
+    let block = NodeValue::HtmlBlock(NodeHtmlBlock {
+        block_type: 6,
+        literal,
+    });
+    arena.alloc(block.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chm::inputs::{InputOptions, md_load};
+    use std::path::Path;
+
+    fn render(markdown: &str) -> (String, Vec<String>) {
+        let (file, _) = md_load(
+            Path::new("src/a.md"),
+            markdown.as_bytes(),
+            &InputOptions::default(),
+        )
+        .unwrap();
+        (
+            String::from_utf8(file.contents).unwrap(),
+            file.metadata.alinks,
+        )
+    }
+
+    #[test]
+    fn directives_are_removed() {
+        let (html, groups) =
+            render("# A\n\n{{#alink Collections; Vec}}\n\nText {{#related}} more\n");
+        assert_eq!(groups, ["Collections", "Vec"]);
+        assert!(html.contains(concat!(
+            r#"<param name="ALink Name" value="Collections">"#,
+            r#"<param name="ALink Name" value="Vec"></OBJECT>"#
+        )));
+        assert!(html.contains("<p>Text <!--chm-related--> more</p>"));
+        assert!(!html.contains("{{#"));
+    }
+
+    #[test]
+    fn related_topics_go_at_the_end_without_a_directive() {
+        let (html, _) = render("# A\n\nIn {{#alink Collections}} text\n\nEnd\n");
+        assert!(html.contains("<p>End</p>\n<!--chm-related-->\n"));
+
+        let (html, groups) = render("# A\n\nText\n");
+        assert!(groups.is_empty());
+        assert!(!html.contains("chm-related"));
+    }
+
+    #[test]
+    fn related_directives_name_their_groups() {
+        let (html, _) =
+            render("# A\n\n{{#alink Collections}}\n\n{{#related Strings; Maps}}\n\nEnd\n");
+        assert!(html.contains("</h1>\n<!--chm-related:Strings;Maps-->\n<p>End</p>"));
+        assert_eq!(html.matches("chm-related").count(), 1);
+    }
+}
@@ -0,0 +1,215 @@
+//! Finds the files a chapter depends on: images, and assets referenced from raw HTML and inline styles
+//!
+//! References are rewritten to point at the place the asset is stored in the project
+use crate::chm::inputs::{InputOptions, css::rewrite_urls, urls::resolve_asset};
+use comrak::nodes::{AstNode, NodeHtmlBlock, NodeValue};
+use regex::{Captures, Regex};
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+static TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)<(img|video|audio|source|track|link|embed|input)\b[^>]*>").unwrap()
+});
+
+static REL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\srel\s*=\s*(?:"[^"]*\b(?:stylesheet|icon)\b[^"]*"|'[^']*\b(?:stylesheet|icon)\b[^']*'|(?:stylesheet|icon)\b)"#).unwrap()
+});
+
+static ELEMENT_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[a-zA-Z][^>]*>").unwrap());
+
+static STYLE_ATTRIBUTE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)(\sstyle\s*=\s*)(?:"([^"]*)"|'([^']*)')"#).unwrap());
+
+static STYLE_ELEMENT_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)(<style\b[^>]*>)(.*?)(</style\s*>)").unwrap());
+
+static ATTRIBUTE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(\s(?:src|poster|href)\s*=\s*)(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap()
+});
+
+/// Collects the assets referenced by the document, rewriting the references
+pub fn collect_assets<'a>(
+    root: &'a AstNode<'a>,
+    path: &Path,
+    options: &InputOptions,
+) -> Vec<PathBuf> {
+    let mut dependencies = vec![];
+    for node in root.descendants() {
+        match &mut node.data.borrow_mut().value {
+            NodeValue::Image(image) => {
+                if let Some((asset, url)) = resolve_asset(path, &image.url, options) {
+                    image.url = url;
+                    if !dependencies.contains(&asset) {
+                        dependencies.push(asset);
+                    }
+                }
+            }
+            NodeValue::HtmlInline(html)
+            | NodeValue::HtmlBlock(NodeHtmlBlock { literal: html, .. }) => {
+                *html = rewrite_html(html, path, options, &mut dependencies);
+            }
+            _ => {}
+        }
+    }
+    dependencies
+}
+
+/// Rewrites the assets referenced by tags, inline styles and `<style>` elements in raw HTML
+fn rewrite_html(
+    html: &str,
+    path: &Path,
+    options: &InputOptions,
+    dependencies: &mut Vec<PathBuf>,
+) -> String {
+    //
+    // Links are only assets when they load a stylesheet or an icon, not for `prev`, `next` or `canonical`
+    let html = TAG_REGEX.replace_all(html, |tag: &Captures<'_>| {
+        if tag[1].eq_ignore_ascii_case("link") && !REL_REGEX.is_match(&tag[0]) {
+            return tag[0].to_string();
+        }
+        ATTRIBUTE_REGEX
+            .replace_all(&tag[0], |attribute: &Captures<'_>| {
+                let Some(url) = (2..=4).find_map(|i| attribute.get(i)) else {
+                    return attribute[0].to_string();
+                };
+                let Some((asset, new_url)) = resolve_asset(path, url.as_str(), options) else {
+                    return attribute[0].to_string();
+                };
+
+                if !dependencies.contains(&asset) {
+                    dependencies.push(asset);
+                }
+                let quote = if attribute.get(2).is_some() {
+                    "\""
+                } else if attribute.get(3).is_some() {
+                    "'"
+                } else {
+                    ""
+                };
+                format!("{}{quote}{new_url}{quote}", &attribute[1])
+            })
+            .to_string()
+    });
+    rewrite_styles(&html, path, options, dependencies)
+}
+
+/// Rewrites the CSS references in `style` attributes and `<style>` elements, leaving the text around them alone
+fn rewrite_styles(
+    html: &str,
+    path: &Path,
+    options: &InputOptions,
+    dependencies: &mut Vec<PathBuf>,
+) -> String {
+    let html = ELEMENT_REGEX.replace_all(html, |element: &Captures<'_>| {
+        STYLE_ATTRIBUTE_REGEX
+            .replace_all(&element[0], |attribute: &Captures<'_>| {
+                let (quote, css) = match attribute.get(2) {
+                    Some(css) => ("\"", css.as_str()),
+                    None => ("'", &attribute[3]),
+                };
+                let css = rewrite_urls(css, path, options, dependencies);
+                format!("{}{quote}{css}{quote}", &attribute[1])
+            })
+            .to_string()
+    });
+    STYLE_ELEMENT_REGEX
+        .replace_all(&html, |style: &Captures<'_>| {
+            let css = rewrite_urls(&style[2], path, options, dependencies);
+            format!("{}{css}{}", &style[1], &style[3])
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(html: &str) -> (String, Vec<PathBuf>) {
+        let options = InputOptions {
+            source_root: Some(PathBuf::from("src")),
+            ..InputOptions::default()
+        };
+        let mut dependencies = vec![];
+        let html = rewrite_html(
+            html,
+            Path::new("src/guide/a.md"),
+            &options,
+            &mut dependencies,
+        );
+        (html, dependencies)
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn rewrites_media_sources() {
+        let (html, dependencies) = rewrite(
+            r#"<img src="/img/a.png" alt="A"><video poster='./poster.png' src=clip.mp4></video>"#,
+        );
+        assert_eq!(
+            html,
+            r#"<img src="../img/a.png" alt="A"><video poster='poster.png' src=clip.mp4></video>"#
+        );
+        assert_eq!(
+            dependencies,
+            paths(&[
+                "src/img/a.png",
+                "src/guide/poster.png",
+                "src/guide/clip.mp4"
+            ])
+        );
+    }
+
+    #[test]
+    fn rewrites_stylesheet_and_icon_links_only() {
+        let (html, dependencies) = rewrite(concat!(
+            r#"<link rel="stylesheet" href="/theme/book.css">"#,
+            r#"<link rel=icon href=favicon.png>"#,
+            r#"<link rel="next" href="/b.html">"#
+        ));
+        assert_eq!(
+            html,
+            concat!(
+                r#"<link rel="stylesheet" href="../theme/book.css">"#,
+                r#"<link rel=icon href=favicon.png>"#,
+                r#"<link rel="next" href="/b.html">"#
+            )
+        );
+        assert_eq!(
+            dependencies,
+            paths(&["src/theme/book.css", "src/guide/favicon.png"])
+        );
+    }
+
+    #[test]
+    fn rewrites_inline_styles_but_not_text() {
+        let (html, dependencies) = rewrite(concat!(
+            r#"<div style="background:url('/img/bg.png')">url(/img/text.png)</div>"#,
+            "<style>body { background: url(/img/body.png) }</style>"
+        ));
+        assert_eq!(
+            html,
+            concat!(
+                r#"<div style="background:url('../img/bg.png')">url(/img/text.png)</div>"#,
+                "<style>body { background: url(../img/body.png) }</style>"
+            )
+        );
+        assert_eq!(dependencies, paths(&["src/img/bg.png", "src/img/body.png"]));
+    }
+
+    #[test]
+    fn leaves_external_urls_alone() {
+        let html = concat!(
+            r#"<img src="https://example.com/a.png"><img src="data:image/png;base64,AAA">"#,
+            r#"<img src="//cdn.example.com/a.png"><img src="file:///etc/passwd">"#,
+            r#"<div style="background: url(data:image/png;base64,AAA), url('http://example.com/x.png')">"#
+        );
+        let (rewritten, dependencies) = rewrite(html);
+        assert_eq!(rewritten, html);
+        assert!(dependencies.is_empty());
+    }
+}
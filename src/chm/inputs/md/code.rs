@@ -0,0 +1,211 @@
+//! Finds Rust identifiers in inline code and Rust code blocks, for the code index
+//!
+//! Items, macros and attributes in code blocks are recorded, with items counting as definitions.
+//! Inline code is recorded when it looks like a path, a call, a macro, an attribute or a type name
+use super::{codeblocks::split_info, headings::anchor_node};
+use crate::chm::contents::CodeIdentifier;
+use comrak::{
+    Arena,
+    nodes::{AstNode, NodeValue},
+};
+use regex::Regex;
+use std::{collections::HashSet, sync::LazyLock};
+
+static PATH_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z_]\w*(?:::[A-Za-z_]\w*)*!?$").unwrap());
+
+static GENERICS_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^<>]*>").unwrap());
+
+static ATTRIBUTE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"#!?\[\s*([A-Za-z_][\w:]*)").unwrap());
+
+static ITEM_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b(?:fn|struct|enum|trait|type|const|static|mod|union)\s+(?:(?:fn|mut)\s+)?([A-Za-z_]\w*)")
+        .unwrap()
+});
+
+static MACRO_RULES_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\bmacro_rules!\s*([A-Za-z_]\w*)").unwrap());
+
+static MACRO_CALL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b([A-Za-z_]\w*)!\s*[(\[{]").unwrap());
+
+static IMPL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\bimpl\b(?:\s*<[^{]*?>)?\s+(?:[\w:]+(?:<[^{]*?>)?\s+for\s+)?([A-Za-z_][\w:]*)")
+        .unwrap()
+});
+
+/// Words that are never identifiers
+const RESERVED: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "main", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+/// Collects the Rust identifiers in the document, anchoring the first sighting of each one.\
+/// Hidden lines must already be removed from the code blocks
+pub fn extract_code_identifiers<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+) -> Vec<CodeIdentifier> {
+    let mut identifiers = vec![];
+    let mut seen = HashSet::new();
+    let mut anchors = 0;
+
+    let nodes = root.descendants().collect::<Vec<_>>();
+    for node in nodes {
+        let found = match &node.data.borrow().value {
+            NodeValue::Code(code) => inline_identifier(&code.literal)
+                .map(|name| vec![(name, false)])
+                .unwrap_or_default(),
+            NodeValue::CodeBlock(block) if split_info(&block.info).0 == "rust" => {
+                block_identifiers(&block.literal)
+            }
+            _ => continue,
+        };
+
+        let found = found
+            .into_iter()
+            .filter(|(name, defined)| seen.insert((name.clone(), *defined)))
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            continue;
+        }
+
+        anchors += 1;
+        let anchor = format!("code-{anchors}");
+        node.insert_before(anchor_node(arena, &anchor));
+
+        identifiers.extend(found.into_iter().map(|(name, defined)| CodeIdentifier {
+            name,
+            anchor: anchor.clone(),
+            defined,
+        }));
+    }
+
+    identifiers
+}
+
+/// The identifier written in inline code, if it looks like one
+fn inline_identifier(code: &str) -> Option<String> {
+    let code = code.trim();
+    if let Some(captures) = ATTRIBUTE_REGEX.captures(code)
+        && code.starts_with('#')
+    {
+        return Some(format!("#[{}]", &captures[1]));
+    }
+
+    //
+    // Drop references, generics and call arguments, like `&mut Vec<T>::push(x)`
+    let mut path = code
+        .trim_start_matches('&')
+        .trim_start_matches("mut ")
+        .to_string();
+    while GENERICS_REGEX.is_match(&path) {
+        path = GENERICS_REGEX.replace_all(&path, "").to_string();
+    }
+    let called = path.ends_with(')');
+    let path = match path.split_once('(') {
+        Some((path, _)) if called => path,
+        _ => path.as_str(),
+    };
+
+    if !PATH_REGEX.is_match(path) || RESERVED.contains(&path) {
+        return None;
+    }
+
+    //
+    // Lone lowercase words are usually variables
+    let notable = called
+        || path.contains("::")
+        || path.ends_with('!')
+        || path.starts_with(char::is_uppercase);
+    notable.then(|| path.to_string())
+}
+
+/// The items, macros and attributes in a Rust code block, and whether each one is defined there
+fn block_identifiers(code: &str) -> Vec<(String, bool)> {
+    let mut found = vec![];
+    let mut impls: Vec<(String, usize)> = vec![];
+    let mut depth = 0;
+    for line in code.lines() {
+        let owner = impls.last().map(|(name, _)| name.as_str());
+        for captures in ITEM_REGEX.captures_iter(line) {
+            let name = &captures[1];
+            if RESERVED.contains(&name) {
+                continue;
+            }
+            match owner {
+                Some(owner) => found.push((format!("{owner}::{name}"), true)),
+                None => found.push((name.to_string(), true)),
+            }
+        }
+        for captures in MACRO_RULES_REGEX.captures_iter(line) {
+            found.push((format!("{}!", &captures[1]), true));
+        }
+        for captures in MACRO_CALL_REGEX.captures_iter(line) {
+            if &captures[1] != "macro_rules" {
+                found.push((format!("{}!", &captures[1]), false));
+            }
+        }
+        for captures in ATTRIBUTE_REGEX.captures_iter(line) {
+            found.push((format!("#[{}]", &captures[1]), false));
+        }
+
+        if let Some(captures) = IMPL_REGEX.captures(line) {
+            let name = captures[1].rsplit("::").next().unwrap_or_default();
+            impls.push((name.to_string(), depth));
+        }
+
+        for c in line.chars() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth = depth.saturating_sub(1);
+                    if impls.last().is_some_and(|(_, opened)| *opened == depth) {
+                        impls.pop();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    found.retain(|identifier| seen.insert(identifier.clone()));
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chm::inputs::{InputOptions, md_load};
+    use std::{collections::HashMap, path::Path};
+
+    fn identifiers(markdown: &str, hidden_lines: HashMap<String, String>) -> Vec<String> {
+        let options = InputOptions {
+            code_identifiers: true,
+            hidden_lines,
+            ..InputOptions::default()
+        };
+        let (file, _) = md_load(Path::new("page.md"), markdown.as_bytes(), &options).unwrap();
+        file.metadata
+            .code_identifiers
+            .into_iter()
+            .map(|identifier| identifier.name)
+            .collect()
+    }
+
+    #[test]
+    fn skips_hidden_lines() {
+        let markdown = "```rust\n# fn hidden() {}\nfn shown() {}\n```\n";
+        assert_eq!(identifiers(markdown, HashMap::new()), ["shown"]);
+
+        let markdown = "```rust\n~ fn hidden() {}\n# fn shown() {}\n```\n";
+        let hidden_lines = HashMap::from([("rust".to_string(), "~".to_string())]);
+        assert_eq!(identifiers(markdown, hidden_lines), ["shown"]);
+
+        let markdown = "```rust,hidelines=!\n!struct Hidden;\nstruct Shown;\n```\n";
+        assert_eq!(identifiers(markdown, HashMap::new()), ["Shown"]);
+    }
+}
@@ -0,0 +1,148 @@
+//! Code blocks, handled the way mdbook does
+//!
+//! Hidden lines are removed: lines starting with `# ` in Rust, where `##` escapes a `#`,
+//! and lines starting with the prefix set for other languages, or with a `hidelines=<prefix>` attribute.
+//! Blocks without a language keep all their lines.\
+//! Examples marked `should_panic` or `compile_fail` get a label
+use comrak::{
+    Arena,
+    nodes::{AstNode, NodeHtmlBlock, NodeValue},
+};
+use std::collections::HashMap;
+
+/// Labels shown above examples, by attribute
+const LABELS: &[(&str, &str)] = &[
+    ("should_panic", "This example panics"),
+    ("compile_fail", "This example deliberately fails to compile"),
+];
+
+/// Removes the hidden lines from code blocks, and labels the examples that are expected to fail.\
+/// `hidden_lines` maps languages to the prefix of their hidden lines
+pub fn prepare_code_blocks<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    hidden_lines: &HashMap<String, String>,
+) {
+    let blocks = root
+        .descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::CodeBlock(_)))
+        .collect::<Vec<_>>();
+    for node in blocks {
+        let labels = {
+            let NodeValue::CodeBlock(block) = &mut node.data.borrow_mut().value else {
+                continue;
+            };
+            let (language, attributes) = split_info(&block.info);
+
+            //
+            // Like mdbook, only blocks labelled with a language have hidden lines
+            if !block.info.trim().is_empty() {
+                let prefix = attributes
+                    .iter()
+                    .find_map(|attribute| attribute.strip_prefix("hidelines="))
+                    .or_else(|| hidden_lines.get(language).map(String::as_str));
+
+                if let Some(prefix) = prefix {
+                    block.literal = hide_prefixed_lines(&block.literal, prefix);
+                } else if language == "rust" {
+                    block.literal = hide_rust_lines(&block.literal);
+                }
+            }
+
+            let labels = LABELS
+                .iter()
+                .filter(|(attribute, _)| attributes.contains(attribute))
+                .map(|(_, label)| *label)
+                .collect::<Vec<_>>();
+
+            //
+            // Fences like `ignore` are Rust, and need to say so to be highlighted
+            if !attributes.is_empty() && language == "rust" && !block.info.starts_with("rust") {
+                block.info = format!("rust,{}", block.info.trim());
+            }
+            labels
+        };
+
+        for label in labels {
+            let block = NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 6,
+                literal: format!("<div class=\"code-label\">{label}</div>\n"),
+            });
+            node.insert_before(arena.alloc(block.into()));
+        }
+    }
+}
+
+/// The language and attributes of a fence like `rust,should_panic`.\
+/// Blocks without a language are Rust, like in mdbook, though they keep their hidden lines
+pub fn split_info(info: &str) -> (&str, Vec<&str>) {
+    let mut parts = info
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty());
+    let language = match parts.next() {
+        Some(first) if !is_rust_attribute(first) => first,
+        Some(first) => return ("rust", std::iter::once(first).chain(parts).collect()),
+        None => "rust",
+    };
+    (language, parts.collect())
+}
+
+/// Attributes that make a block Rust, even when they come first like `ignore`
+fn is_rust_attribute(part: &str) -> bool {
+    matches!(
+        part,
+        "ignore" | "no_run" | "should_panic" | "compile_fail" | "editable"
+    ) || part.starts_with("edition")
+}
+
+/// Removes Rust's hidden lines, which start with `# ` or are just `#`.\
+/// A line starting with `##` shows with one `#`
+fn hide_rust_lines(code: &str) -> String {
+    let mut shown = String::with_capacity(code.len());
+    for line in code.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("##") {
+            shown.push_str(&line.replacen("##", "#", 1));
+        } else if !trimmed.starts_with("# ") && trimmed.trim_end() != "#" {
+            shown.push_str(line);
+        }
+    }
+    shown
+}
+
+/// Removes the lines starting with a prefix
+fn hide_prefixed_lines(code: &str, prefix: &str) -> String {
+    code.split_inclusive('\n')
+        .filter(|line| !line.trim_start().starts_with(prefix))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chm::inputs::{HighlightMode, Highlighting, InputOptions, md_load};
+    use std::path::Path;
+
+    fn render(markdown: &str) -> String {
+        let options = InputOptions {
+            highlighting: Highlighting {
+                mode: HighlightMode::Off,
+                ..Highlighting::default()
+            },
+            ..InputOptions::default()
+        };
+        let (file, _) = md_load(Path::new("page.md"), markdown.as_bytes(), &options).unwrap();
+        String::from_utf8(file.contents).unwrap()
+    }
+
+    #[test]
+    fn unlabelled_blocks_keep_their_lines() {
+        let html = render("```\n# install deps\ncargo install mdbook\n```\n");
+        assert!(html.contains("# install deps\ncargo install mdbook"));
+
+        let html = render("```rust\n# fn hidden() {}\nfn shown() {}\n```\n");
+        assert!(!html.contains("hidden"));
+
+        let html = render("```ignore\n# fn hidden() {}\nfn shown() {}\n```\n");
+        assert!(!html.contains("hidden"));
+    }
+}
@@ -0,0 +1,160 @@
+//! Replaces diagram code blocks with images, rendered when the book is built
+//!
+//! The source stays below each image, collapsed. Diagrams that cannot be rendered are left as code, with a warning
+use crate::chm::{
+    contents::{File, FileMetadata},
+    inputs::DiagramRendering,
+    utilities::{escape_html, normalize_path, relative_url},
+};
+use comrak::{
+    Arena,
+    nodes::{AstNode, NodeHtmlBlock, NodeValue},
+};
+use std::path::{Path, PathBuf};
+
+/// Folder of the project holding the diagram images
+const DIAGRAM_FOLDER: &str = "_diagrams";
+
+/// Replaces the diagrams in the document with images, and returns the image files
+pub fn render_diagrams<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    document: &Path,
+    options: &DiagramRendering,
+) -> Vec<File> {
+    let mut images: Vec<File> = vec![];
+
+    let nodes = root.descendants().collect::<Vec<_>>();
+    for (number, node) in nodes.into_iter().enumerate() {
+        let (language, source) = match &node.data.borrow().value {
+            NodeValue::CodeBlock(block) => {
+                let language = block.info.split([',', ' ']).next().unwrap_or_default();
+                (language.to_string(), block.literal.clone())
+            }
+            _ => continue,
+        };
+        if !options.is_diagram(&language) {
+            continue;
+        }
+
+        let diagram = match options.render(&language, &source) {
+            Ok(diagram) => diagram,
+            Err(e) => {
+                eprintln!(
+                    "Warning: Could not render the `{language}` diagram in `{}`: {e}",
+                    document.display()
+                );
+                continue;
+            }
+        };
+
+        let Some(name) = options.file_name(&language, &source) else {
+            continue;
+        };
+        let path = PathBuf::from(DIAGRAM_FOLDER).join(name);
+        let url = normalize_path(document).map_or_else(
+            || path.to_string_lossy().to_string(),
+            |document| relative_url(Path::new(&document), &path),
+        );
+        let source = escape_html(&source);
+        let html = format!(
+            "<div class=\"diagram\"><img src=\"{url}\" alt=\"{source}\" style=\"width: {:.0}px; height: {:.0}px;\"></div>\n\
+             <div class=\"collapsible\">\
+             <input type=\"checkbox\" class=\"collapsible-toggle\" id=\"diagram-source-{number}\">\
+             <label for=\"diagram-source-{number}\">Diagram source</label>\
+             <div class=\"collapsible-body\"><pre><code>{source}</code></pre></div>\
+             </div>\n",
+            diagram.width, diagram.height
+        );
+        let block = NodeValue::HtmlBlock(NodeHtmlBlock {
+            block_type: 6,
+            literal: html,
+        });
+        node.insert_before(arena.alloc(block.into()));
+        node.detach();
+
+        if !images.iter().any(|image| image.path == path) {
+            images.push(File {
+                path,
+                contents: diagram.image,
+                metadata: FileMetadata::default(),
+            });
+        }
+    }
+
+    images
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chm::inputs::{InputOptions, md_load};
+    use std::path::Path;
+
+    fn render(markdown: &str) -> (String, Vec<String>) {
+        let (file, _) = md_load(
+            Path::new("src/guide/a.md"),
+            markdown.as_bytes(),
+            &InputOptions::default(),
+        )
+        .unwrap();
+        let images = file
+            .metadata
+            .generated
+            .iter()
+            .map(|image| image.path.to_string_lossy().replace('\\', "/"))
+            .collect();
+        let html = String::from_utf8(file.contents).unwrap();
+        (html[html.find("<body>").unwrap()..].to_string(), images)
+    }
+
+    #[test]
+    fn fences_become_images_with_the_source_collapsed() {
+        let (html, images) = render("```dot\ndigraph { a -> b }\n```\n");
+        assert_eq!(images.len(), 1);
+        assert!(images[0].starts_with("_diagrams/"));
+        assert!(
+            Path::new(&images[0])
+                .extension()
+                .is_some_and(|extension| extension == "svg")
+        );
+        assert!(html.contains(&format!(
+            "<div class=\"diagram\"><img src=\"../../{}\" alt=\"digraph {{ a -&gt; b }}\n\"",
+            images[0]
+        )));
+        assert!(html.contains("<div class=\"collapsible\">"));
+        assert!(html.contains(
+            "<div class=\"collapsible-body\"><pre><code>digraph { a -&gt; b }\n</code></pre></div>"
+        ));
+        assert!(!html.contains("language-dot"));
+    }
+
+    #[test]
+    fn alt_text_is_escaped() {
+        let (html, images) = render("```bob\n\"a\" <--> 'b' & c\n```\n");
+        assert_eq!(images.len(), 1);
+        assert!(html.contains(r#"alt="&quot;a&quot; &lt;--&gt; &apos;b&apos; &amp; c"#));
+    }
+
+    #[test]
+    fn identical_diagrams_share_an_image() {
+        let (html, images) = render(
+            "```dot\ndigraph { a -> b }\n```\n\n```graphviz\ndigraph { a -> b }\n```\n\n```dot\ndigraph { a -> b }\n```\n",
+        );
+        assert_eq!(images.len(), 2);
+        assert_eq!(
+            html.matches(&format!("src=\"../../{}\"", images[0]))
+                .count(),
+            2
+        );
+        assert_eq!(html.matches("<div class=\"collapsible\">").count(), 3);
+    }
+
+    #[test]
+    fn failed_renders_stay_as_code() {
+        let (html, images) = render("```dot\ndigraph {}\n```\n");
+        assert!(images.is_empty());
+        assert!(!html.contains("<img"));
+        assert!(html.contains("<code class=\"language-dot\">"));
+        assert!(html.contains("digraph "));
+    }
+}
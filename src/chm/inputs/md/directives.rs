@@ -0,0 +1,82 @@
+//! Directives written in the text of a document, like `{{#index Ownership}}`
+use comrak::{
+    Arena,
+    nodes::{AstNode, NodeValue},
+};
+use regex::{Captures, Regex};
+
+/// Replaces every match of `regex` in the text of the document with the node returned by `replace`,
+/// or removes it if there is none.\
+/// Code is left alone, so directives can still be shown in examples, and paragraphs left empty are removed
+pub fn replace_in_text<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    regex: &Regex,
+    mut replace: impl FnMut(&Captures<'_>) -> Option<&'a AstNode<'a>>,
+) {
+    let texts = root
+        .descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::Text(_)))
+        .collect::<Vec<_>>();
+    for node in texts {
+        let text = match &node.data.borrow().value {
+            NodeValue::Text(text) if regex.is_match(text) => text.clone(),
+            _ => continue,
+        };
+
+        let mut current = node;
+        let mut first = true;
+        let mut last = 0;
+        for captures in regex.captures_iter(&text) {
+            let Some(directive) = captures.get(0) else {
+                continue;
+            };
+
+            let before = &text[last..directive.start()];
+            if first {
+                node.data.borrow_mut().value = NodeValue::Text(before.to_string());
+                first = false;
+            } else if !before.is_empty() {
+                let text_node = arena.alloc(NodeValue::Text(before.to_string()).into());
+                current.insert_after(text_node);
+                current = text_node;
+            }
+
+            if let Some(replacement) = replace(&captures) {
+                current.insert_after(replacement);
+                current = replacement;
+            }
+            last = directive.end();
+        }
+
+        let after = &text[last..];
+        if !after.is_empty() {
+            let text_node = arena.alloc(NodeValue::Text(after.to_string()).into());
+            current.insert_after(text_node);
+        }
+
+        if let Some(parent) = node.parent()
+            && matches!(parent.data.borrow().value, NodeValue::Paragraph)
+            && parent.children().all(is_blank)
+        {
+            parent.detach();
+        }
+    }
+}
+
+/// True for whitespace and line breaks
+pub fn is_blank<'a>(node: &'a AstNode<'a>) -> bool {
+    match &node.data.borrow().value {
+        NodeValue::Text(text) => text.trim().is_empty(),
+        NodeValue::SoftBreak | NodeValue::LineBreak => true,
+        _ => false,
+    }
+}
+
+/// Split a `;` separated list of values, trimming them and dropping empty ones
+pub fn split_list(source: &str) -> impl Iterator<Item = &str> {
+    source
+        .split(';')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
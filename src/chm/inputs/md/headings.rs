@@ -0,0 +1,186 @@
+//! Heading IDs, generated the same way as mdbook's HTML renderer so links written for the web book keep working
+//!
+//! Headings can end with an attribute block like `{#custom-id .index}`, which is removed from the text
+use crate::chm::contents::Heading;
+use comrak::{
+    Arena,
+    nodes::{AstNode, NodeCode, NodeValue},
+};
+use regex::Regex;
+use std::{collections::HashMap, sync::LazyLock};
+
+static ATTRIBUTES_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s*\{([^{}]*)\}\s*$").unwrap());
+
+static ATTRIBUTE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\.([\w-]+)|#([\w-]+)|([\w-]+)="([^"]*)"|(\S+)"#).unwrap());
+
+/// Attributes found at the end of a heading, like `{#custom-id .index}`
+#[derive(Debug, Default)]
+pub struct HeadingAttributes {
+    /// Custom ID, replacing the generated one
+    pub id: Option<String>,
+
+    /// Classes, like `index`
+    pub classes: Vec<String>,
+
+    /// Other `key="value"` attributes
+    pub values: HashMap<String, String>,
+}
+impl HeadingAttributes {
+    /// Parse the contents of an attribute block, or `None` if it is not one.\
+    /// Empty blocks like the `{}` of `fn foo() {}` are text, not attributes
+    fn parse(block: &str) -> Option<Self> {
+        let mut attributes = Self::default();
+        let mut found = false;
+        for captures in ATTRIBUTE_REGEX.captures_iter(block) {
+            found = true;
+            if captures.get(5).is_some() {
+                return None;
+            } else if let Some(class) = captures.get(1) {
+                attributes.classes.push(class.as_str().to_string());
+            } else if let Some(id) = captures.get(2) {
+                attributes.id = Some(id.as_str().to_string());
+            } else {
+                attributes
+                    .values
+                    .insert(captures[3].to_string(), captures[4].to_string());
+            }
+        }
+        found.then_some(attributes)
+    }
+}
+
+/// A heading in the document, after its ID was assigned
+#[derive(Debug)]
+pub struct ParsedHeading {
+    /// Level, ID and text of the heading
+    pub heading: Heading,
+
+    /// Attributes removed from the end of the heading
+    pub attributes: HeadingAttributes,
+}
+
+/// Gives every heading an ID, anchored at the start of the heading
+pub fn assign_heading_ids<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+) -> Vec<ParsedHeading> {
+    let mut id_counter = HashMap::new();
+    let mut headings = vec![];
+    for node in root.descendants() {
+        let NodeValue::Heading(heading) = node.data.borrow().value else {
+            continue;
+        };
+
+        let attributes = strip_heading_attributes(node).unwrap_or_default();
+        let title = plain_text(node);
+        let id = match &attributes.id {
+            Some(id) => id.clone(),
+            None => unique_id(&title, &mut id_counter),
+        };
+
+        node.prepend(anchor_node(arena, &id));
+        headings.push(ParsedHeading {
+            heading: Heading {
+                level: heading.level,
+                id,
+                title,
+            },
+            attributes,
+        });
+    }
+
+    headings
+}
+
+/// Generates an ID like mdbook does, adding a counter to repeated IDs
+fn unique_id(title: &str, id_counter: &mut HashMap<String, usize>) -> String {
+    let id = mdbook::utils::normalize_id(title.trim().trim_start_matches('#').trim());
+    let count = id_counter.entry(id.clone()).or_insert(0);
+    let unique = match *count {
+        0 => id,
+        count => format!("{id}-{count}"),
+    };
+    *count += 1;
+    unique
+}
+
+/// Removes the `{...}` attribute block at the end of a heading, if it has one
+fn strip_heading_attributes<'a>(heading: &'a AstNode<'a>) -> Option<HeadingAttributes> {
+    let last = heading.last_child()?;
+    let mut data = last.data.borrow_mut();
+    let NodeValue::Text(text) = &mut data.value else {
+        return None;
+    };
+
+    let captures = ATTRIBUTES_REGEX.captures(text)?;
+    let attributes = HeadingAttributes::parse(&captures[1])?;
+    let start = captures.get(0)?.start();
+    text.truncate(start);
+    Some(attributes)
+}
+
+/// The text content of a node, without any formatting
+pub fn plain_text<'a>(node: &'a AstNode<'a>) -> String {
+    let mut text = String::new();
+    for child in node.descendants() {
+        match &child.data.borrow().value {
+            NodeValue::Text(value) | NodeValue::Code(NodeCode { literal: value, .. }) => {
+                text.push_str(value);
+            }
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+/// An empty named anchor, which links and index entries can point at
+pub fn anchor_node<'a>(arena: &'a Arena<AstNode<'a>>, name: &str) -> &'a AstNode<'a> {
+    arena.alloc(NodeValue::HtmlInline(format!(r#"<a name="{name}" id="{name}"></a>"#)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chm::inputs::{InputOptions, md_load};
+    use std::path::Path;
+
+    fn headings(markdown: &str) -> Vec<(String, String)> {
+        let (file, _) = md_load(
+            Path::new("page.md"),
+            markdown.as_bytes(),
+            &InputOptions::default(),
+        )
+        .unwrap();
+        file.metadata
+            .headings
+            .into_iter()
+            .map(|heading| (heading.id, heading.title))
+            .collect()
+    }
+
+    #[test]
+    fn parses_attribute_blocks() {
+        let attributes = HeadingAttributes::parse(r#"#id .class key="v""#).unwrap();
+        assert_eq!(attributes.id.as_deref(), Some("id"));
+        assert_eq!(attributes.classes, ["class"]);
+        assert_eq!(attributes.values["key"], "v");
+
+        assert!(HeadingAttributes::parse("").is_none());
+        assert!(HeadingAttributes::parse("  ").is_none());
+        assert!(HeadingAttributes::parse("#id not-an-attribute").is_none());
+    }
+
+    #[test]
+    fn empty_braces_stay_in_the_heading() {
+        assert_eq!(
+            headings("## Using {}\n\n## fn foo() { }\n\n## Custom {#custom .index}\n"),
+            [
+                ("using-".to_string(), "Using {}".to_string()),
+                ("fn-foo--".to_string(), "fn foo() { }".to_string()),
+                ("custom".to_string(), "Custom".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Resolves the links in a chapter
+//!
+//! Links to other chapters are rewritten to point at their HTML topics, keeping the query and fragment.\
+//! Links with a scheme like `https:` or `mailto:`, anchors in the same chapter, and links to other files are left alone
+use crate::chm::{
+    contents::AnchorLink,
+    inputs::urls::{RelativeUrl, percent_decode},
+    utilities::normalize_path,
+};
+use comrak::nodes::{AstNode, NodeValue};
+use regex::Regex;
+use std::{collections::HashSet, path::Path, sync::LazyLock};
+
+static ANCHOR_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\s(?:id|name)\s*=\s*["']([^"']+)["']"#).unwrap());
+
+/// Rewrites the links to other chapters in the document, returning the links pointing at anchors.\
+/// If the chapters of the book are known, links to markdown files that are not one of them are reported
+pub fn resolve_links<'a>(
+    root: &'a AstNode<'a>,
+    path: &Path,
+    chapters: Option<&HashSet<String>>,
+) -> Vec<AnchorLink> {
+    let own_topic = normalize_path(path).map(|path| topic_path(&path));
+    let mut anchor_links = vec![];
+    for node in root.descendants() {
+        let NodeValue::Link(ref mut link) = node.data.borrow_mut().value else {
+            continue;
+        };
+        let url = link.url.clone();
+        let Some(parts) = RelativeUrl::parse(&url) else {
+            continue;
+        };
+
+        let target = if parts.path.is_empty() {
+            own_topic.clone()
+        } else if let Some((rewritten, target)) = resolve_link(&url, parts, path, chapters) {
+            link.url = rewritten;
+            target
+        } else {
+            None
+        };
+
+        if let Some(target) = target
+            && let Some(anchor) = parts.fragment.strip_prefix('#')
+            && !anchor.is_empty()
+        {
+            anchor_links.push(AnchorLink {
+                url: url.clone(),
+                target,
+                anchor: percent_decode(anchor),
+            });
+        }
+    }
+
+    anchor_links
+}
+
+/// The rewritten URL of a link to a chapter, and the normalized path of its topic if the chapter exists.\
+/// `None` if the link is left alone
+fn resolve_link(
+    url: &str,
+    parts: RelativeUrl<'_>,
+    path: &Path,
+    chapters: Option<&HashSet<String>>,
+) -> Option<(String, Option<String>)> {
+    if parts.path.starts_with(['/', '\\']) {
+        return None;
+    }
+
+    let target = Path::new(parts.path);
+    if !target
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("md"))
+    {
+        return None;
+    }
+
+    let chapter = normalize_path(path).unwrap_or_default();
+    let parent = chapter.rsplit_once('/').map_or("", |(parent, _)| parent);
+    let resolved = normalize_path(&Path::new(parent).join(percent_decode(parts.path)));
+    let found = match chapters {
+        Some(chapters) => resolved.filter(|resolved| chapters.contains(resolved)),
+        None => resolved,
+    };
+    if chapters.is_some() && found.is_none() {
+        eprintln!(
+            "Warning: `{}` links to `{url}`, which is not a chapter in the book",
+            path.display()
+        );
+    }
+
+    let stem = &parts.path[..parts.path.len() - "md".len()];
+    let rewritten = format!("{stem}html{}{}", parts.query, parts.fragment);
+    Some((rewritten, found.map(|found| topic_path(&found))))
+}
+
+/// Normalized path of the topic rendered from a normalized chapter path
+fn topic_path(chapter: &str) -> String {
+    Path::new(chapter)
+        .with_extension("html")
+        .to_string_lossy()
+        .to_string()
+}
+
+/// The names of the anchors in a rendered document, from both `id` and `name` attributes
+pub fn collect_anchors(html: &str) -> HashSet<String> {
+    ANCHOR_REGEX
+        .captures_iter(html)
+        .map(|captures| captures[1].to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::collect_anchors;
+    use crate::chm::inputs::{InputOptions, md_load};
+    use std::{collections::HashSet, path::Path, sync::Arc};
+
+    fn render(markdown: &str) -> (String, Vec<(String, String)>) {
+        let chapters = ["src/guide/a.md", "src/b.md", "src/guide/other.md"]
+            .into_iter()
+            .map(ToString::to_string)
+            .collect::<HashSet<_>>();
+        let options = InputOptions {
+            chapters: Some(Arc::new(chapters)),
+            ..InputOptions::default()
+        };
+        let (file, _) =
+            md_load(Path::new("src/guide/a.md"), markdown.as_bytes(), &options).unwrap();
+        let links = file
+            .metadata
+            .anchor_links
+            .into_iter()
+            .map(|link| (link.target, link.anchor))
+            .collect();
+        (String::from_utf8(file.contents).unwrap(), links)
+    }
+
+    #[test]
+    fn rewrites_chapter_links_keeping_query_and_fragment() {
+        let (html, links) = render(
+            "[a](other.md#section) [b](../b.md?x=1#Top%20Part) [c](other.md) [d](#local) [e](missing.md)\n",
+        );
+        assert!(html.contains(r#"<a href="other.html#section">a</a>"#));
+        assert!(html.contains(r#"<a href="../b.html?x=1#Top%20Part">b</a>"#));
+        assert!(html.contains(r#"<a href="other.html">c</a>"#));
+        assert!(html.contains(r##"<a href="#local">d</a>"##));
+        assert!(html.contains(r#"<a href="missing.html">e</a>"#));
+        assert_eq!(
+            links,
+            [
+                ("src/guide/other.html".to_string(), "section".to_string()),
+                ("src/b.html".to_string(), "Top Part".to_string()),
+                ("src/guide/a.html".to_string(), "local".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_other_links_alone() {
+        let (html, links) = render(
+            "[a](mailto:me@example.com) [b](ftp://example.com/x.md) [c](../image.png) [d](file.txt) [e](//cdn.example.com/x.md) [f](https://example.com/y.md)\n",
+        );
+        for href in [
+            "mailto:me@example.com",
+            "ftp://example.com/x.md",
+            "../image.png",
+            "file.txt",
+            "//cdn.example.com/x.md",
+            "https://example.com/y.md",
+        ] {
+            assert!(
+                html.contains(&format!(r#"href="{href}""#)),
+                "{href} in {html}"
+            );
+        }
+        assert!(links.is_empty());
+    }
+
+    #[test]
+    fn anchors_come_from_ids_and_names() {
+        let anchors = collect_anchors(
+            r#"<h2 id="usage">Usage</h2><a name='top'></a><div data-id="no" class="id"></div>"#,
+        );
+        assert_eq!(
+            anchors,
+            HashSet::from(["usage".to_string(), "top".to_string()])
+        );
+
+        let (html, _) = render("# Title\n\n## Usage {#custom}\n");
+        let anchors = collect_anchors(&html);
+        assert!(anchors.contains("title"));
+        assert!(anchors.contains("custom"));
+    }
+}
@@ -0,0 +1,194 @@
+//! Index terms marked by the author, using `{{#index Ownership; Vec > push}}` in the text,
+//! or `{.index}` and `{index="Ownership; borrowing"}` attributes at the end of headings.\
+//! A term like `Lifetimes => Borrowing` redirects to another term, as a "See Also" entry
+//!
+//! Markers are removed from the document, and replaced by anchors the index entries point at.
+//! Terms with an empty level, like `Vec >`, are reported and skipped
+use super::{
+    directives::{replace_in_text, split_list},
+    headings::{ParsedHeading, anchor_node},
+};
+use crate::chm::contents::IndexMarker;
+use comrak::{Arena, nodes::AstNode};
+use regex::Regex;
+use std::sync::LazyLock;
+
+static MARKER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{#index\s+([^}]*)\}\}").unwrap());
+
+/// Removes the index markers from the document, returning the terms they declared
+pub fn extract_index_markers<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    headings: &[ParsedHeading],
+) -> Vec<IndexMarker> {
+    let mut markers = vec![];
+    let mut anchors = 0;
+    let mut next_anchor = || {
+        anchors += 1;
+        format!("idx-{anchors}")
+    };
+
+    //
+    // Heading attributes point at the ID of the heading
+    for heading in headings {
+        let attributes = &heading.attributes;
+        let mut terms = attributes
+            .values
+            .get("index")
+            .map(|terms| parse_terms(terms))
+            .unwrap_or_default();
+        if attributes.classes.iter().any(|class| class == "index") {
+            terms.push((vec![heading.heading.title.clone()], None));
+        }
+
+        markers.extend(terms.into_iter().map(|(terms, see_also)| IndexMarker {
+            terms,
+            anchor: heading.heading.id.clone(),
+            see_also,
+        }));
+    }
+
+    //
+    // Then inline markers, which can appear in any text
+    replace_in_text(arena, root, &MARKER_REGEX, |captures| {
+        let anchor = next_anchor();
+        markers.extend(
+            parse_terms(&captures[1])
+                .into_iter()
+                .map(|(terms, see_also)| IndexMarker {
+                    terms,
+                    anchor: anchor.clone(),
+                    see_also,
+                }),
+        );
+        Some(anchor_node(arena, &anchor))
+    });
+
+    markers
+}
+
+/// Split a list of terms like `Ownership; Vec > push; Lifetimes => Borrowing` into their levels,
+/// and the term each one redirects to
+fn parse_terms(source: &str) -> Vec<(Vec<String>, Option<String>)> {
+    split_list(source)
+        .filter_map(|term| {
+            let parsed = parse_term(term);
+            if parsed.is_none() {
+                eprintln!("Warning: Index term `{term}` has an empty part, and is not indexed");
+            }
+            parsed
+        })
+        .collect()
+}
+
+/// Split a single term into its levels, and the term it redirects to
+fn parse_term(term: &str) -> Option<(Vec<String>, Option<String>)> {
+    let (term, see_also) = match term.split_once("=>") {
+        Some((term, see_also)) => (term, Some(see_also.trim().to_string())),
+        None => (term, None),
+    };
+    let levels = term
+        .split('>')
+        .map(|level| level.trim().to_string())
+        .collect::<Vec<_>>();
+
+    let empty = |text: &String| text.is_empty();
+    if levels.iter().any(empty) || see_also.as_ref().is_some_and(empty) {
+        return None;
+    }
+    Some((levels, see_also))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chm::inputs::{InputOptions, md_load};
+    use std::path::Path;
+
+    /// Terms, anchor and redirect of a marker
+    type Marker = (Vec<String>, String, Option<String>);
+
+    fn markers(markdown: &str) -> (Vec<Marker>, String) {
+        let (file, _) = md_load(
+            Path::new("page.md"),
+            markdown.as_bytes(),
+            &InputOptions::default(),
+        )
+        .unwrap();
+        let markers = file
+            .metadata
+            .index_markers
+            .into_iter()
+            .map(|marker| (marker.terms, marker.anchor, marker.see_also))
+            .collect();
+        (markers, String::from_utf8(file.contents).unwrap())
+    }
+
+    fn terms(levels: &[&str]) -> Vec<String> {
+        levels.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn parses_nested_terms_and_see_also() {
+        assert_eq!(
+            parse_terms(" Ownership ;Vec > push > at end;; Lifetimes => Borrowing > rules "),
+            [
+                (terms(&["Ownership"]), None),
+                (terms(&["Vec", "push", "at end"]), None),
+                (terms(&["Lifetimes"]), Some("Borrowing > rules".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_terms_with_empty_parts() {
+        assert_eq!(
+            parse_terms("Vec >; > push; Vec >> push; Lifetimes =>; => Borrowing; Kept"),
+            [(terms(&["Kept"]), None)]
+        );
+    }
+
+    #[test]
+    fn paragraph_markers_are_anchored_in_place() {
+        let (found, html) = markers("Moving {{#index Ownership; Vec > push}}values.\n");
+        assert_eq!(
+            found,
+            [
+                (terms(&["Ownership"]), "idx-1".to_string(), None),
+                (terms(&["Vec", "push"]), "idx-1".to_string(), None),
+            ]
+        );
+        assert!(html.contains(r#"Moving <a name="idx-1" id="idx-1"></a>values."#));
+        assert!(!html.contains("#index"));
+
+        let (found, html) = markers("{{#index Lifetimes => Borrowing}}\n\n`{{#index Code}}`\n");
+        assert_eq!(
+            found,
+            [(
+                terms(&["Lifetimes"]),
+                "idx-1".to_string(),
+                Some("Borrowing".to_string())
+            )]
+        );
+        assert!(html.contains("<code>{{#index Code}}</code>"));
+    }
+
+    #[test]
+    fn heading_markers_point_at_the_heading() {
+        let (found, html) = markers(
+            "## Moves {{#index Ownership}}\n\n## Borrowing {.index}\n\n## Slices {index=\"Vec > slice; Arrays\"}\n",
+        );
+        assert_eq!(
+            found,
+            [
+                (terms(&["Borrowing"]), "borrowing".to_string(), None),
+                (terms(&["Vec", "slice"]), "slices".to_string(), None),
+                (terms(&["Arrays"]), "slices".to_string(), None),
+                (terms(&["Ownership"]), "idx-1".to_string(), None),
+            ]
+        );
+        assert!(html.contains(">Borrowing</h2>"));
+        assert!(html.contains(">Slices</h2>"));
+    }
+}
@@ -0,0 +1,188 @@
+//! Replaces math with images, typeset when the book is built
+//!
+//! Each distinct formula becomes one image under `_math`, named after its content so topics share them.\
+//! Math that cannot be typeset is shown as its escaped TeX source in a `<code class="math-source">`, with a warning.
+//! No `MathML` is written, since the viewer cannot show it
+use crate::chm::{
+    contents::{File, FileMetadata},
+    inputs::MathRendering,
+    utilities::{content_hash, escape_html, normalize_path, relative_url},
+};
+use comrak::{
+    Arena,
+    nodes::{AstNode, NodeHtmlBlock, NodeValue},
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Folder of the project holding the math images
+const MATH_FOLDER: &str = "_math";
+
+/// Replaces the math in the document with images, and returns the image files
+pub fn render_math<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    document: &Path,
+    options: &MathRendering,
+) -> Vec<File> {
+    let mut images = vec![];
+    let mut rendered = HashMap::new();
+
+    let nodes = root.descendants().collect::<Vec<_>>();
+    for node in nodes {
+        let (tex, display, block) = match &node.data.borrow().value {
+            NodeValue::Math(math) => (math.literal.clone(), math.display_math, false),
+            NodeValue::CodeBlock(block) if block.info.trim() == "math" => {
+                (block.literal.clone(), true, true)
+            }
+            _ => continue,
+        };
+
+        let html = rendered
+            .entry((tex.clone(), display))
+            .or_insert_with(|| {
+                let (html, image) = math_html(&tex, display, document, options);
+                images.extend(image);
+                html
+            })
+            .clone();
+
+        let value = if block {
+            NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 6,
+                literal: format!("<div class=\"math-display\">{html}</div>\n"),
+            })
+        } else if display {
+            NodeValue::HtmlInline(format!("<span class=\"math-display\">{html}</span>"))
+        } else {
+            NodeValue::HtmlInline(html)
+        };
+        node.insert_before(arena.alloc(value.into()));
+        node.detach();
+    }
+
+    images
+}
+
+/// The HTML showing some math, and the image it needs
+fn math_html(
+    tex: &str,
+    display: bool,
+    document: &Path,
+    options: &MathRendering,
+) -> (String, Option<File>) {
+    let tex = tex.trim();
+    let alt = escape_html(tex);
+    let rendered = options.source(tex, display).and_then(|source| {
+        let extension = options
+            .format
+            .extension()
+            .ok_or_else(|| "Math rendering is turned off".to_string())?;
+        let path = PathBuf::from(format!(
+            "{MATH_FOLDER}/{}.{extension}",
+            content_hash(format!("{source}{:?}{}", options.format, options.png_scale).as_bytes()),
+        ));
+        options.render(&source, display).map(|math| (path, math))
+    });
+
+    match rendered {
+        Ok((path, math)) => {
+            let url = normalize_path(document).map_or_else(
+                || path.to_string_lossy().to_string(),
+                |document| relative_url(Path::new(&document), &path),
+            );
+            let mut style = format!("width: {:.2}pt; height: {:.2}pt;", math.width, math.height);
+            if !display {
+                style = format!("{style} vertical-align: -{:.2}pt;", math.depth);
+            }
+            let html =
+                format!("<img class=\"math\" src=\"{url}\" alt=\"{alt}\" style=\"{style}\">");
+            let image = File {
+                path,
+                contents: math.image,
+                metadata: FileMetadata::default(),
+            };
+            (html, Some(image))
+        }
+        Err(e) => {
+            eprintln!(
+                "Warning: Could not render the math `{tex}` in `{}`: {e}",
+                document.display()
+            );
+            (format!("<code class=\"math-source\">{alt}</code>"), None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chm::inputs::{InputOptions, md_load};
+    use std::path::Path;
+
+    fn render(markdown: &str) -> (String, Vec<String>) {
+        let (file, _) = md_load(
+            Path::new("src/guide/a.md"),
+            markdown.as_bytes(),
+            &InputOptions::default(),
+        )
+        .unwrap();
+        let images = file
+            .metadata
+            .generated
+            .iter()
+            .map(|image| image.path.to_string_lossy().replace('\\', "/"))
+            .collect();
+        let html = String::from_utf8(file.contents).unwrap();
+        (html[html.find("<body>").unwrap()..].to_string(), images)
+    }
+
+    #[test]
+    fn identical_formulas_share_an_image() {
+        let (html, images) = render("Inline $x^2$ and again $x^2$, then $x < y$\n\n$$x^2$$\n");
+        assert_eq!(images.len(), 3);
+        assert!(images.iter().all(|image| {
+            image.starts_with("_math/")
+                && Path::new(image)
+                    .extension()
+                    .is_some_and(|extension| extension == "svg")
+        }));
+        assert_eq!(
+            html.matches(&format!("src=\"../../{}\"", images[0]))
+                .count(),
+            2
+        );
+        assert!(html.contains(r#"alt="x &lt; y""#));
+    }
+
+    #[test]
+    fn inline_math_sits_on_the_baseline() {
+        let (html, _) = render("Inline $x^2$\n\n$$x^2$$\n");
+        let inline = &html[html.find("Inline").unwrap()..html.find("</p>").unwrap()];
+        assert!(inline.contains("vertical-align: -"));
+        assert!(html.contains(r#"<p><span class="math-display"><img class="math""#));
+        assert_eq!(html.matches("vertical-align").count(), 1);
+    }
+
+    #[test]
+    fn math_blocks_are_displayed() {
+        let (html, images) = render("```math\nx^2\n```\n");
+        assert_eq!(images.len(), 1);
+        assert!(html.contains(&format!(
+            r#"<div class="math-display"><img class="math" src="../../{}" alt="x^2""#,
+            images[0]
+        )));
+    }
+
+    #[test]
+    fn untranslatable_math_stays_as_source() {
+        let (html, images) = render("Bad $\\nosuchcommand{x} < y$\n");
+        assert!(images.is_empty());
+        assert!(
+            html.contains(
+                r#"<p>Bad <code class="math-source">\nosuchcommand{x} &lt; y</code></p>"#
+            )
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! URL handling shared by the input formats
+//!
+//! Splits relative URLs into their parts, and resolves references to assets like images and stylesheets
+use super::InputOptions;
+use crate::chm::utilities::{normalize_path, relative_url};
+use regex::Regex;
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+static SCHEME_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z][A-Za-z0-9+.-]*:").unwrap());
+
+/// A relative URL, split into its parts
+#[derive(Debug, Clone, Copy)]
+pub struct RelativeUrl<'u> {
+    /// Path of the target, still percent-encoded
+    pub path: &'u str,
+
+    /// The query, including the `?`
+    pub query: &'u str,
+
+    /// The fragment, including the `#`
+    pub fragment: &'u str,
+}
+impl<'u> RelativeUrl<'u> {
+    /// Split a URL into its parts, or `None` if it is not relative to the current document
+    pub fn parse(url: &'u str) -> Option<Self> {
+        if url.starts_with("//") || SCHEME_REGEX.is_match(url) {
+            return None;
+        }
+
+        let (url, fragment) = match url.find('#') {
+            Some(start) => url.split_at(start),
+            None => (url, ""),
+        };
+        let (path, query) = match url.find('?') {
+            Some(start) => url.split_at(start),
+            None => (url, ""),
+        };
+        Some(Self {
+            path,
+            query,
+            fragment,
+        })
+    }
+}
+
+/// Resolves a reference to an asset from a document.\
+/// Returns the path of the asset to include, and the URL pointing at it from the document,
+/// or `None` if the reference is not to a local file
+///
+/// Paths starting with `/` are relative to the source root, like in the HTML book.\
+/// Other paths are relative to the document, or to the source root if the file is only found there
+pub fn resolve_asset(
+    document: &Path,
+    url: &str,
+    options: &InputOptions,
+) -> Option<(PathBuf, String)> {
+    let source_root = options.source_root.as_deref();
+    let parts = RelativeUrl::parse(url.trim())?;
+    if parts.path.is_empty() {
+        return None;
+    }
+
+    let path = percent_decode(parts.path);
+    let document_key = normalize_path(document).filter(|_| document.is_relative());
+    let asset = if let Some(path) = path.strip_prefix(['/', '\\']) {
+        source_root?.join(path)
+    } else {
+        let parent = document_key.as_deref().map_or_else(
+            || document.parent().unwrap_or(Path::new("")).to_path_buf(),
+            |document| PathBuf::from(document.rsplit_once('/').map_or("", |(parent, _)| parent)),
+        );
+        locate(
+            document,
+            url,
+            &parent.join(&path),
+            source_root.map(|root| root.join(&path)),
+        )
+    };
+
+    //
+    // Assets are stored at their place in the book, so the document and the asset keep their relative location
+    //
+    // Assets outside the book are mounted in the project, if they are in an allowed directory
+    let asset = match &options.confinement {
+        Some(confinement) if normalize_path(&asset).is_none() => {
+            confinement.mount(&asset).unwrap_or(asset)
+        }
+        _ => asset,
+    };
+    if let Some(document) = document_key
+        && let Some(asset) = normalize_path(&asset)
+    {
+        let url = format!(
+            "{}{}{}",
+            relative_url(Path::new(&document), Path::new(&asset)).replace(' ', "%20"),
+            parts.query,
+            parts.fragment
+        );
+        return Some((PathBuf::from(asset), url));
+    }
+
+    Some((asset, url.to_string()))
+}
+
+/// Picks between an asset beside the document and one in the source root, warning if both exist
+fn locate(document: &Path, url: &str, beside: &Path, in_root: Option<PathBuf>) -> PathBuf {
+    let Some(in_root) = in_root else {
+        return beside.to_path_buf();
+    };
+
+    match (beside.exists(), in_root.exists()) {
+        (false, true) => in_root,
+        (true, true) if normalize_path(beside) != normalize_path(&in_root) => {
+            eprintln!(
+                "Warning: `{url}` in `{}` could be `{}` or `{}`. Using the first, like the HTML book",
+                document.display(),
+                beside.display(),
+                in_root.display()
+            );
+            beside.to_path_buf()
+        }
+        _ => beside.to_path_buf(),
+    }
+}
+
+/// Decode the `%XX` escapes in a URL path
+pub fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = escape {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> InputOptions {
+        InputOptions {
+            source_root: Some(PathBuf::from("src")),
+            ..InputOptions::default()
+        }
+    }
+
+    #[test]
+    fn root_relative_paths_start_at_the_source_root() {
+        let document = Path::new("src/guide/a.md");
+        assert_eq!(
+            resolve_asset(document, "/images/logo%20big.png?v=2", &options()),
+            Some((
+                PathBuf::from("src/images/logo big.png"),
+                "../images/logo%20big.png?v=2".to_string()
+            ))
+        );
+        assert_eq!(
+            resolve_asset(document, "img/a.png", &options()),
+            Some((
+                PathBuf::from("src/guide/img/a.png"),
+                "img/a.png".to_string()
+            ))
+        );
+        assert_eq!(
+            resolve_asset(document, "https://example.com/a.png", &options()),
+            None
+        );
+        assert_eq!(resolve_asset(document, "#top", &options()), None);
+    }
+
+    #[test]
+    fn assets_are_found_beside_the_document_or_in_the_root() {
+        let dir = std::env::temp_dir().join(format!("mdbook-chm-urls-{}", std::process::id()));
+        for folder in ["src/guide/img", "src/img"] {
+            std::fs::create_dir_all(dir.join(folder)).unwrap();
+        }
+        for file in [
+            "src/img/root.png",
+            "src/img/both.png",
+            "src/guide/img/both.png",
+        ] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+
+        let document = dir.join("src/guide/a.md");
+        let beside = |name: &str| dir.join("src/guide/img").join(name);
+        let in_root = |name: &str| Some(dir.join("src/img").join(name));
+        let found = [
+            locate(
+                &document,
+                "img/root.png",
+                &beside("root.png"),
+                in_root("root.png"),
+            ),
+            locate(
+                &document,
+                "img/both.png",
+                &beside("both.png"),
+                in_root("both.png"),
+            ),
+            locate(
+                &document,
+                "img/none.png",
+                &beside("none.png"),
+                in_root("none.png"),
+            ),
+        ];
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(
+            found,
+            [
+                dir.join("src/img/root.png"),
+                beside("both.png"),
+                beside("none.png")
+            ]
+        );
+    }
+
+    #[test]
+    fn images_link_to_their_place_in_the_book() {
+        let (file, dependencies) = crate::chm::inputs::md_load(
+            Path::new("src/guide/a.md"),
+            b"![logo](/images/logo.png)\n",
+            &options(),
+        )
+        .unwrap();
+        assert_eq!(dependencies, [PathBuf::from("src/images/logo.png")]);
+        let html = String::from_utf8(file.contents).unwrap();
+        assert!(html.contains(r#"<img src="../images/logo.png" alt="logo" />"#));
+    }
+}
@@ -1,71 +1,436 @@
-//! Finds `rare` keywords in a document set.
-//! Defined as any word appearing in only one document in the set
+//! Finds keywords in a document set.
+//! By default, defined as any word appearing in only one document in the set. See [`KeywordStrategy`] for alternatives
 //!
-
+//! Only the visible text of each document is considered; markup, scripts and styles are skipped
+use crate::chm::{
+    ChmLanguage,
+    html::{HtmlEvent, walk_html},
+};
+use normalize::Normalizer;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap},
     path::Path,
+    sync::Arc,
 };
 
-/// Finds `rare` keywords in a document set.
+mod normalize;
+pub use normalize::{language_stop_words, stemmer_algorithm};
+
+mod segmentation;
+pub use segmentation::{Segmentation, is_complex_script};
+
+mod stopwords;
+
+mod strategies;
+pub use strategies::*;
+
+/// Settings controlling which words a [`Keyworder`] picks
+#[derive(Debug, Clone)]
+pub struct KeywordSettings {
+    /// Decides which of the extracted words get indexed
+    pub strategy: Arc<dyn KeywordStrategy>,
+
+    /// Words shorter than this are ignored
+    pub min_length: usize,
+
+    /// The language of the documents
+    pub language: ChmLanguage,
+
+    /// How the text of the documents is split into words
+    pub segmentation: Segmentation,
+
+    /// Words that are never indexed, in lowercase
+    pub stop_words: BTreeSet<String>,
+
+    /// Merge words that only differ by case, displaying the most common form
+    pub case_folding: bool,
+
+    /// Merge words sharing a stem, if there is a stemmer for the language
+    pub stemming: bool,
+
+    /// How extracted keywords interact with index terms marked by the author
+    pub markers: MarkerMode,
+
+    /// Which headings inside topics get their own index entry
+    pub headings: HeadingEntries,
+
+    /// How Rust identifiers found in code are indexed
+    pub code: CodeEntries,
+}
+
+/// How Rust identifiers found in code are grouped in the index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CodeIndexMode {
+    /// Identifiers are not indexed
+    #[default]
+    Off,
+
+    /// All identifiers are grouped under a single entry
+    Grouped,
+
+    /// Paths like `std::mem::swap` are grouped under their crate, and the rest under a single entry
+    PerCrate,
+}
+
+/// How Rust identifiers found in code are indexed
+#[derive(Debug, Clone)]
+pub struct CodeEntries {
+    /// How identifiers are grouped, if at all
+    pub mode: CodeIndexMode,
+
+    /// Title of the entry holding the identifiers
+    pub group: String,
+}
+impl Default for CodeEntries {
+    fn default() -> Self {
+        Self {
+            mode: CodeIndexMode::default(),
+            group: "Code".to_string(),
+        }
+    }
+}
+impl CodeEntries {
+    /// Splits an identifier into the entry it is grouped under, and its name within the group
+    #[must_use]
+    pub fn group<'a>(&'a self, identifier: &'a str) -> (&'a str, &'a str) {
+        if self.mode == CodeIndexMode::PerCrate
+            && let Some((krate, rest)) = identifier.split_once("::")
+            && krate.starts_with(|c: char| c.is_ascii_lowercase())
+            && !matches!(krate, "self" | "super" | "crate")
+        {
+            return (krate, rest);
+        }
+        (&self.group, identifier)
+    }
+}
+
+/// Which headings inside topics get their own index entry
+#[derive(Debug, Clone)]
+pub struct HeadingEntries {
+    /// Deepest heading level indexed, like 3 for `###`.\
+    /// Level 1 headings are topic titles and are never indexed here, so 1 disables heading entries
+    pub max_level: u8,
+
+    /// Nest heading entries under the title of their topic
+    pub nested: bool,
+
+    /// Headings left out, by lowercase text or by `topic.html#id` path
+    pub exclude: BTreeSet<String>,
+}
+impl Default for HeadingEntries {
+    fn default() -> Self {
+        Self {
+            max_level: 1,
+            nested: false,
+            exclude: BTreeSet::new(),
+        }
+    }
+}
+
+/// How extracted keywords interact with index terms marked by the author
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MarkerMode {
+    /// Index both the marked terms and the extracted keywords
+    #[default]
+    Combine,
+
+    /// Topics with marked terms are not searched for keywords
+    Replace,
+}
+impl KeywordSettings {
+    /// Settings for a language, using its built-in stop words and case folding
+    #[must_use]
+    pub fn for_language(language: ChmLanguage) -> Self {
+        Self {
+            language,
+            segmentation: Segmentation::for_language(language),
+            stop_words: language_stop_words(language)
+                .map(str::to_lowercase)
+                .collect(),
+            case_folding: true,
+            ..Self::default()
+        }
+    }
+}
+impl Default for KeywordSettings {
+    fn default() -> Self {
+        Self {
+            strategy: Arc::new(DocumentFrequency::default()),
+            min_length: 1,
+            language: ChmLanguage::default(),
+            segmentation: Segmentation::default(),
+            stop_words: BTreeSet::new(),
+            case_folding: false,
+            stemming: false,
+            markers: MarkerMode::default(),
+            headings: HeadingEntries::default(),
+            code: CodeEntries::default(),
+        }
+    }
+}
+
+/// Finds keywords in a document set.
 ///
-/// Defined as any word appearing in only one document in the set
+/// By default, defined as any word appearing in only one document in the set
 #[derive(Debug, Clone, Default)]
 pub struct Keyworder<'src> {
     keywords: Vec<KeywordProperties<'src>>,
-    index: HashMap<&'src str, usize>,
+    index: HashMap<String, usize>,
+    documents: HashMap<&'src Path, usize>,
+    normalizer: Normalizer,
 }
 
 impl<'src> Keyworder<'src> {
     /// Create a new keyworder
     #[must_use]
     pub fn new() -> Self {
+        Self::with_settings(KeywordSettings::default())
+    }
+
+    /// Create a new keyworder with the given settings
+    #[must_use]
+    pub fn with_settings(settings: KeywordSettings) -> Self {
         Self {
             keywords: Vec::new(),
             index: HashMap::new(),
+            documents: HashMap::new(),
+            normalizer: Normalizer::new(settings),
         }
     }
 
-    /// Process a document and add its keywords to the index
-    pub fn process(&mut self, path: &'src Path, content: &'src str) {
-        let word_regex = regex::Regex::new(r"\b[\w-]+\b").unwrap();
-        let word_iter = word_regex.find_iter(content).map(|m| m.as_str());
+    /// Process an HTML document and add the keywords in its visible text to the index
+    pub fn process(&mut self, path: &'src Path, content: &str) {
+        let mut nesting = TextContext::default();
+        let mut sightings: Vec<(String, KeywordSighting<'src>)> = vec![];
+        let mut forms: Vec<HashMap<String, usize>> = vec![];
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut length = 0;
 
-        let mut seen = HashSet::new();
-        for word in word_iter {
-            if seen.contains(word) {
-                continue;
+        walk_html(content, |event| {
+            let text = match event {
+                HtmlEvent::Start { name, .. } => {
+                    nesting.enter(name);
+                    return;
+                }
+                HtmlEvent::End { name } => {
+                    nesting.leave(name);
+                    return;
+                }
+                HtmlEvent::Text(text) => text,
+            };
+            if nesting.hidden > 0 {
+                return;
             }
-            seen.insert(word);
 
-            if let Some(index) = self.index.get(word) {
-                self.keywords[*index].seen_in.push(path);
-            } else {
+            for word in self.normalizer.settings.segmentation.segment(text) {
+                length += 1;
+                let Some(term) = self.normalizer.term(word) else {
+                    continue;
+                };
+
+                let index = *seen.entry(term.clone()).or_insert_with(|| {
+                    sightings.push((term, KeywordSighting::new(path)));
+                    forms.push(HashMap::new());
+                    sightings.len() - 1
+                });
+                *forms[index].entry(word.to_string()).or_default() += 1;
+
+                let sighting = &mut sightings[index].1;
+                sighting.count += 1;
+                if nesting.heading > 0 {
+                    sighting.in_heading += 1;
+                }
+                if nesting.code > 0 {
+                    sighting.in_code += 1;
+                }
+                if nesting.emphasis > 0 {
+                    sighting.emphasized += 1;
+                }
+            }
+        });
+
+        *self.documents.entry(path).or_default() += length;
+        for ((term, sighting), forms) in sightings.into_iter().zip(forms) {
+            let index = *self.index.entry(term).or_insert_with(|| {
                 self.keywords.push(KeywordProperties {
-                    keyword: word,
-                    seen_in: vec![path],
+                    keyword: String::new(),
+                    seen_in: vec![],
+                    forms: HashMap::new(),
                 });
-                self.index.insert(word, self.keywords.len() - 1);
+                self.keywords.len() - 1
+            });
+
+            let keyword = &mut self.keywords[index];
+            keyword.seen_in.push(sighting);
+            for (form, count) in forms {
+                *keyword.forms.entry(form).or_default() += count;
             }
+            keyword.update_display_form();
         }
     }
 
-    /// Get the keywords in the keyworder visible within the threshold
+    /// Get the keywords picked by the strategy, sorted alphabetically
     #[must_use]
-    pub fn visible_keywords(&self) -> Vec<&KeywordProperties<'src>> {
-        let iter = self.keywords.iter();
-        let mut visible: Vec<_> = iter.filter(|k| k.seen_in.len() == 1).collect();
-        visible.sort_by(|a, b| a.keyword.cmp(b.keyword));
+    pub fn visible_keywords(&self) -> Vec<KeywordProperties<'src>> {
+        let mut visible = self
+            .normalizer
+            .settings
+            .strategy
+            .select(&self.keywords, &self.documents);
+        visible.sort_by(|a, b| a.keyword.cmp(&b.keyword));
         visible
     }
 }
 
+/// Tracks which kinds of elements the current text is nested in
+#[derive(Debug, Default)]
+struct TextContext {
+    hidden: usize,
+    heading: usize,
+    code: usize,
+    emphasis: usize,
+}
+impl TextContext {
+    fn counter(&mut self, tag: &str) -> Option<&mut usize> {
+        match tag {
+            "head" | "script" | "style" | "template" | "noscript" => Some(&mut self.hidden),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Some(&mut self.heading),
+            "code" | "pre" | "kbd" | "samp" | "var" => Some(&mut self.code),
+            "em" | "strong" | "b" | "i" | "mark" | "dfn" => Some(&mut self.emphasis),
+            _ => None,
+        }
+    }
+
+    fn enter(&mut self, tag: &str) {
+        if let Some(counter) = self.counter(tag) {
+            *counter += 1;
+        }
+    }
+
+    fn leave(&mut self, tag: &str) {
+        if let Some(counter) = self.counter(tag) {
+            *counter = counter.saturating_sub(1);
+        }
+    }
+}
+
 /// An extracted keyword and its properties
 #[derive(Debug, Clone)]
 pub struct KeywordProperties<'src> {
     /// The keyword itself
-    pub keyword: &'src str,
+    pub keyword: String,
 
     /// The documents it appears in
-    pub seen_in: Vec<&'src Path>,
+    pub seen_in: Vec<KeywordSighting<'src>>,
+
+    /// Every spelling of the keyword found, and how often it appeared
+    pub forms: HashMap<String, usize>,
+}
+impl KeywordProperties<'_> {
+    /// Display the keyword using its most common spelling
+    fn update_display_form(&mut self) {
+        if let Some((form, _)) = self
+            .forms
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+        {
+            self.keyword.clone_from(form);
+        }
+    }
+
+    /// Returns a copy of the keyword keeping only some of its documents, or `None` if none are left
+    #[must_use]
+    pub fn filtered(&self, mut keep: impl FnMut(&KeywordSighting<'_>) -> bool) -> Option<Self> {
+        let seen_in = self
+            .seen_in
+            .iter()
+            .filter(|sighting| keep(sighting))
+            .cloned()
+            .collect::<Vec<_>>();
+        if seen_in.is_empty() {
+            None
+        } else {
+            Some(Self {
+                keyword: self.keyword.clone(),
+                seen_in,
+                forms: self.forms.clone(),
+            })
+        }
+    }
+}
+
+/// How a keyword appears within a single document
+#[derive(Debug, Clone)]
+pub struct KeywordSighting<'src> {
+    /// The document the keyword appears in
+    pub path: &'src Path,
+
+    /// Total number of appearances in the document
+    pub count: usize,
+
+    /// Number of appearances inside headings
+    pub in_heading: usize,
+
+    /// Number of appearances inside code
+    pub in_code: usize,
+
+    /// Number of appearances inside emphasized text
+    pub emphasized: usize,
+}
+impl<'src> KeywordSighting<'src> {
+    fn new(path: &'src Path) -> Self {
+        Self {
+            path,
+            count: 0,
+            in_heading: 0,
+            in_code: 0,
+            emphasized: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_visible_text_is_indexed() {
+        let mut keyworder = Keyworder::new();
+        keyworder.process(
+            Path::new("a.html"),
+            r#"<html><head><title>Heading</title><style>.callout { color: red }</style></head>
+            <body><div class="callout" style="margin: 0"><a href="target.html">Visible</a></div>
+            <script>var hidden = 1;</script></body></html>"#,
+        );
+
+        let keywords = keyworder
+            .visible_keywords()
+            .into_iter()
+            .map(|keyword| keyword.keyword)
+            .collect::<Vec<_>>();
+        assert_eq!(keywords, ["Visible"]);
+    }
+
+    #[test]
+    fn sightings_know_their_context() {
+        let mut keyworder = Keyworder::new();
+        keyworder.process(
+            Path::new("a.html"),
+            "<h2>Vec</h2><p><em>Vec</em> and <code>Vec</code>, Vec &amp; more</p>",
+        );
+
+        let keywords = keyworder.visible_keywords();
+        let vec = keywords
+            .iter()
+            .find(|keyword| keyword.keyword == "Vec")
+            .unwrap();
+        let sighting = &vec.seen_in[0];
+        assert_eq!(sighting.count, 4);
+        assert_eq!(sighting.in_heading, 1);
+        assert_eq!(sighting.emphasized, 1);
+        assert_eq!(sighting.in_code, 1);
+        assert!(!keywords.iter().any(|keyword| keyword.keyword == "amp"));
+    }
 }
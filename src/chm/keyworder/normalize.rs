@@ -0,0 +1,119 @@
+//! Turns the words found in a document into index terms
+use super::{KeywordSettings, is_complex_script, stopwords::builtin_stop_words};
+use crate::chm::ChmLanguage;
+use rust_stemmers::{Algorithm, Stemmer};
+
+/// Returns the stemming algorithm for a language, if there is one
+#[must_use]
+pub fn stemmer_algorithm(language: ChmLanguage) -> Option<Algorithm> {
+    let algorithm = match language.primary_code() {
+        "ar" => Algorithm::Arabic,
+        "da" => Algorithm::Danish,
+        "nl" => Algorithm::Dutch,
+        "en" => Algorithm::English,
+        "fi" => Algorithm::Finnish,
+        "fr" => Algorithm::French,
+        "de" => Algorithm::German,
+        "el" => Algorithm::Greek,
+        "hu" => Algorithm::Hungarian,
+        "it" => Algorithm::Italian,
+        "nb" => Algorithm::Norwegian,
+        "pt" => Algorithm::Portuguese,
+        "ro" => Algorithm::Romanian,
+        "ru" => Algorithm::Russian,
+        "es" => Algorithm::Spanish,
+        "sv" => Algorithm::Swedish,
+        "tr" => Algorithm::Turkish,
+        _ => return None,
+    };
+    Some(algorithm)
+}
+
+/// Returns the built-in stop words for a language, or an empty list if there are none
+pub fn language_stop_words(language: ChmLanguage) -> impl Iterator<Item = &'static str> {
+    builtin_stop_words(language.primary_code())
+        .unwrap_or_default()
+        .split_whitespace()
+}
+
+/// Applies the filtering, case folding and stemming rules of a [`KeywordSettings`] to words
+pub(super) struct Normalizer {
+    pub settings: KeywordSettings,
+    stemmer: Option<Stemmer>,
+}
+impl Normalizer {
+    pub fn new(settings: KeywordSettings) -> Self {
+        let stemmer = if settings.stemming {
+            stemmer_algorithm(settings.language).map(Stemmer::create)
+        } else {
+            None
+        };
+        Self { settings, stemmer }
+    }
+
+    /// Returns the term a word is indexed under, or `None` if it should not be indexed
+    pub fn term(&self, word: &str) -> Option<String> {
+        if !word.chars().any(char::is_alphabetic) {
+            return None;
+        }
+
+        //
+        // In scripts written without spaces the segmenter finds the words, and one character can be a whole word
+        let min_length = if word.chars().any(is_complex_script) {
+            1
+        } else {
+            self.settings.min_length
+        };
+        if word.chars().count() < min_length {
+            return None;
+        }
+
+        let lowercase = word.to_lowercase();
+        if self.settings.stop_words.contains(&lowercase) {
+            return None;
+        }
+
+        let term = if self.settings.case_folding {
+            lowercase
+        } else {
+            word.to_string()
+        };
+        match &self.stemmer {
+            Some(stemmer) => Some(stemmer.stem(&term).into_owned()),
+            None => Some(term),
+        }
+    }
+}
+impl Clone for Normalizer {
+    fn clone(&self) -> Self {
+        Self::new(self.settings.clone())
+    }
+}
+impl Default for Normalizer {
+    fn default() -> Self {
+        Self::new(KeywordSettings::default())
+    }
+}
+impl std::fmt::Debug for Normalizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Normalizer")
+            .field("settings", &self.settings)
+            .field("stemmer", &self.stemmer.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_single_character_cjk_words() {
+        let mut settings = KeywordSettings::for_language(ChmLanguage::from_code("zh-cn").unwrap());
+        settings.min_length = 3;
+        let normalizer = Normalizer::new(settings);
+
+        assert_eq!(normalizer.term("书").as_deref(), Some("书"));
+        assert_eq!(normalizer.term("ab"), None);
+    }
+}
@@ -0,0 +1,141 @@
+//! Splits text into words
+//!
+//! Most languages separate words with spaces and punctuation, but Chinese, Japanese and Thai do not.
+//! For those, a dictionary or n-grams are used to find the word boundaries.
+//!
+//! This only affects the generated index. Native full-text search is built by the compiler,
+//! using the word breaker of the project `Language`
+use crate::chm::ChmLanguage;
+use icu_segmenter::{WordSegmenter, WordSegmenterBorrowed, options::WordBreakInvariantOptions};
+use regex::Regex;
+use std::sync::LazyLock;
+
+static WORD_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b[\w-]+\b").unwrap());
+static DICTIONARY: LazyLock<WordSegmenterBorrowed<'static>> =
+    LazyLock::new(|| WordSegmenter::new_dictionary(WordBreakInvariantOptions::default()));
+
+/// How text is split into words
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Segmentation {
+    /// Words are separated by spaces and punctuation
+    #[default]
+    Words,
+
+    /// Use the built-in dictionaries for Chinese, Japanese and Thai, and spaces otherwise
+    Dictionary,
+
+    /// Runs of Chinese, Japanese or Thai characters are split into overlapping pairs of characters
+    Bigram,
+}
+impl Segmentation {
+    /// Picks the segmentation suited to a language
+    #[must_use]
+    pub fn for_language(language: ChmLanguage) -> Self {
+        match language.primary_code() {
+            "zh" | "ja" | "th" => Self::Dictionary,
+            _ => Self::Words,
+        }
+    }
+
+    /// Split text into words, skipping spaces and punctuation
+    pub fn segment<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        match self {
+            Self::Words => WORD_REGEX.find_iter(text).map(|m| m.as_str()).collect(),
+
+            Self::Dictionary => {
+                let mut words = vec![];
+                let mut start = 0;
+                for (end, word_type) in DICTIONARY.segment_str(text).iter_with_word_type() {
+                    if word_type.is_word_like() {
+                        words.push(&text[start..end]);
+                    }
+                    start = end;
+                }
+                words
+            }
+
+            Self::Bigram => {
+                let mut words = vec![];
+                let mut rest = text;
+                while !rest.is_empty() {
+                    //
+                    // Alternate between runs of complex script, and everything else
+                    let complex = rest.starts_with(is_complex_script);
+                    let end = rest
+                        .find(|c| is_complex_script(c) != complex)
+                        .unwrap_or(rest.len());
+                    let (run, tail) = rest.split_at(end);
+                    if complex {
+                        words.extend(bigrams(run));
+                    } else {
+                        words.extend(WORD_REGEX.find_iter(run).map(|m| m.as_str()));
+                    }
+                    rest = tail;
+                }
+                words
+            }
+        }
+    }
+}
+
+/// Returns every pair of adjacent characters in a run, or the run itself if it is a single character
+fn bigrams(run: &str) -> Vec<&str> {
+    let offsets = run
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([run.len()])
+        .collect::<Vec<_>>();
+    if offsets.len() <= 3 {
+        return vec![run];
+    }
+
+    offsets
+        .windows(3)
+        .map(|window| &run[window[0]..window[2]])
+        .collect()
+}
+
+/// True for characters of scripts written without spaces between words
+#[must_use]
+pub fn is_complex_script(c: char) -> bool {
+    matches!(c,
+        '\u{0E00}'..='\u{0E7F}' // Thai
+        | '\u{3040}'..='\u{30FF}' // Hiragana and Katakana
+        | '\u{3400}'..='\u{4DBF}' // CJK extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK unified ideographs
+        | '\u{F900}'..='\u{FAFF}' // CJK compatibility ideographs
+        | '\u{20000}'..='\u{2FFFF}' // CJK extensions B and later
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dictionary_finds_words() {
+        let segment = |text| Segmentation::Dictionary.segment(text);
+        assert_eq!(segment("今天天气很好"), ["今天", "天气", "很好"]);
+        assert_eq!(
+            segment("日本語のテキストを読みます"),
+            ["日本語", "の", "テキスト", "を", "読み", "ます"]
+        );
+        assert_eq!(segment("ภาษาไทยอ่านง่าย"), ["ภาษา", "ไทย", "อ่าน", "ง่าย"]);
+        assert_eq!(segment("使用Vec存储数据"), ["使用", "Vec", "存储", "数据"]);
+    }
+
+    #[test]
+    fn bigrams_split_complex_runs_only() {
+        let segment = |text| Segmentation::Bigram.segment(text);
+        assert_eq!(
+            segment("使用Vec存储数据"),
+            ["使用", "Vec", "存储", "储数", "数据"]
+        );
+        assert_eq!(segment("Rust, 是 my-lang"), ["Rust", "是", "my-lang"]);
+        assert_eq!(
+            Segmentation::Words.segment("使用Vec存储数据"),
+            ["使用Vec存储数据"]
+        );
+    }
+}
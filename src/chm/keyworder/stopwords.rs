@@ -0,0 +1,112 @@
+//! Built-in stop-word lists, keyed by primary language code
+//!
+//! These are short lists of the most common function words, meant to keep the index clean.
+//! Books can extend them with their own lists.
+
+/// Returns the built-in stop words for a primary language code like `en`, if there is a list for it
+#[must_use]
+pub fn builtin_stop_words(primary_code: &str) -> Option<&'static str> {
+    let list = match primary_code {
+        "en" => ENGLISH,
+        "de" => GERMAN,
+        "fr" => FRENCH,
+        "es" => SPANISH,
+        "it" => ITALIAN,
+        "pt" => PORTUGUESE,
+        "nl" => DUTCH,
+        "sv" => SWEDISH,
+        "da" => DANISH,
+        "nb" => NORWEGIAN,
+        "fi" => FINNISH,
+        "ru" => RUSSIAN,
+        _ => return None,
+    };
+    Some(list)
+}
+
+const ENGLISH: &str = "
+a about above after again against all am an and any are as at be because been before being below
+between both but by can could did do does doing down during each few for from further had has have
+having he her here hers herself him himself his how i if in into is it its itself just let me more
+most my myself no nor not now of off on once only or other our ours ourselves out over own same she
+should so some such than that the their theirs them themselves then there these they this those
+through to too under until up very was we were what when where which while who whom why will with
+would you your yours yourself yourselves also may might must shall us using use used via etc
+";
+
+const GERMAN: &str = "
+aber alle allem allen aller alles als also am an ander andere anderem anderen anderer anderes auch
+auf aus bei bin bis bist da damit dann das dass dein deine dem den der des dessen dich die dies
+diese diesem diesen dieser dieses dir doch dort du durch ein eine einem einen einer eines er es
+euer eure für hatte hatten hier hin hinter ich ihr ihre im in ist ja jede jedem jeden jeder jedes
+jener jetzt kann kein keine können machen man manche mein meine mich mir mit muss nach nicht nichts
+noch nun nur ob oder ohne sehr sein seine sich sie sind so solche soll sondern um und uns unser
+unter viel vom von vor war waren warst was weg weil weiter welche wenn wer werde werden wie wieder
+will wir wird wo wollen zu zum zur zwar zwischen
+";
+
+const FRENCH: &str = "
+au aux avec ce ces cet cette dans de des du elle elles en est et être eu il ils je la le les leur
+leurs lui ma mais me même mes moi mon ne nos notre nous on ou où par pas pour qu que qui sa se ses
+son sont sur ta te tes toi ton tu un une vos votre vous été étaient était sans sous aussi comme
+plus peut donc ainsi alors car ceci cela
+";
+
+const SPANISH: &str = "
+a al algo algunas algunos ante antes como con contra cual cuando de del desde donde durante e el
+ella ellas ellos en entre era es esa esas ese eso esos esta estas este esto estos fue fueron ha han
+hasta hay la las le les lo los más me mi mis mucho muy nada ni no nos nosotros o otra otros para
+pero poco por porque que quien se ser si sin sobre su sus también te tiene tu un una uno unos y ya
+";
+
+const ITALIAN: &str = "
+a ad al alla alle anche che chi ci come con contro cui da dal dalla dei del della delle di dove e
+ed era gli ha hanno i il in io la le lei lo loro lui ma mi ne negli nei nel nella nelle noi non o
+per più quale quando quello questa questo se si sono su sua sue sui sul sulla suo tra tu un una
+uno voi
+";
+
+const PORTUGUESE: &str = "
+a ao aos as até com como da das de dela delas dele deles depois do dos e ela elas ele eles em
+entre era essa essas esse esses esta este eu foi for há isso isto já lhe mais mas me mesmo meu
+minha muito na nas nem no nos nós o os ou para pela pelas pelo pelos por qual quando que quem se
+sem seu seus sua suas também te tem um uma você
+";
+
+const DUTCH: &str = "
+aan al als bij dan dat de der deze die dit doch door dus een en er had heb heeft hem het hij hoe
+hun ik in is ja je kan maar me men met mij na naar niet nog nu of om omdat ons ook op over te tot
+u uit van veel voor want was wat we wel werd wie wij worden zal ze zich zij zijn zo zonder
+";
+
+const SWEDISH: &str = "
+alla att av blev bli den denna det dig din där eller en ett från för har hade han hans henne
+hennes honom hur här i icke inte jag kan man med mig min mot mycket ni nu när och om oss på sig
+sin sitt skulle som så till under upp ut var vi vid vilken vara är över
+";
+
+const DANISH: &str = "
+af alle at blev bliver da de dem den denne der deres det dette dig din disse dog du efter eller en
+end er et for fra ham han hans har havde hende hendes her hos hun hvad hvis hvor i ikke ind jeg
+kan man mange med meget men mig min mod ned noget nu når og også om op os over på sig sin skal
+som så til ud under var vi ved være
+";
+
+const NORWEGIAN: &str = "
+alle at av bare ble blir da de dei den denne der deres det dette di din du eller en er et etter
+for fra før han hans har hun hva hvis hvor i ikke inn jeg kan kunne man med meg men mot mye nå når
+og også om opp oss over på seg sin skal som så til under ut var ved vi være
+";
+
+const FINNISH: &str = "
+ei ja jo kanssa kun mikä mitä mutta niin nyt se sen siinä siitä sitä tai tämä tämän että he hän
+joka jos kuin me minä mukaan myös ne niiden noin ole olen oli olivat olla on ovat sekä sinä te
+vaan vai voi
+";
+
+const RUSSIAN: &str = "
+а без бы был была были было быть в вам вас весь во вот все всего всех вы где да для до его ее если
+есть еще же за здесь и из или им их к как ко когда который кто ли мне мы на над нам нас не него нее
+нет ни них но ну о об однако он она они оно от по под при с со так также такой там те тем то того
+тоже той только том ты у уже хотя чем что чтобы эта эти это я
+";
@@ -0,0 +1,288 @@
+//! Strategies deciding which extracted keywords end up in the index
+use super::{KeywordProperties, KeywordSighting};
+use std::{collections::HashMap, path::Path};
+
+/// Decides which of the extracted keywords are added to the index, and which documents they point to
+pub trait KeywordStrategy: std::fmt::Debug + Send + Sync {
+    /// Select the keywords to index.\
+    /// `documents` holds the number of words in each processed document
+    fn select<'src>(
+        &self,
+        keywords: &[KeywordProperties<'src>],
+        documents: &HashMap<&'src Path, usize>,
+    ) -> Vec<KeywordProperties<'src>>;
+}
+
+/// The built-in strategies, selectable by name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeywordStrategyKind {
+    /// See [`HeadingsOnly`]
+    HeadingsOnly,
+
+    /// See [`TfIdf`]
+    TfIdf,
+
+    /// See [`DocumentFrequency`]
+    #[default]
+    DocumentFrequency,
+
+    /// See [`Off`]
+    Off,
+}
+
+/// Only index words that appear in headings, pointing at the documents with those headings
+#[derive(Debug, Clone)]
+pub struct HeadingsOnly {
+    /// Skip words found in the headings of more documents than this
+    pub max_documents: usize,
+}
+impl Default for HeadingsOnly {
+    fn default() -> Self {
+        Self { max_documents: 3 }
+    }
+}
+impl KeywordStrategy for HeadingsOnly {
+    fn select<'src>(
+        &self,
+        keywords: &[KeywordProperties<'src>],
+        _documents: &HashMap<&'src Path, usize>,
+    ) -> Vec<KeywordProperties<'src>> {
+        keywords
+            .iter()
+            .filter_map(|keyword| keyword.filtered(|sighting| sighting.in_heading > 0))
+            .filter(|keyword| keyword.seen_in.len() <= self.max_documents)
+            .collect()
+    }
+}
+
+/// Scores each word in each document by TF-IDF, and indexes the best words of every document
+///
+/// Words in headings and emphasized text count extra
+#[derive(Debug, Clone)]
+pub struct TfIdf {
+    /// Maximum number of keywords pointing at a single document
+    pub max_per_topic: usize,
+
+    /// Minimum score for a word to be considered
+    pub min_score: f64,
+}
+impl TfIdf {
+    const HEADING_WEIGHT: f64 = 3.0;
+    const EMPHASIS_WEIGHT: f64 = 1.0;
+
+    #[allow(clippy::cast_precision_loss)]
+    fn score(sighting: &KeywordSighting<'_>, document_length: usize, idf: f64) -> f64 {
+        let weighted = sighting.count as f64
+            + sighting.in_heading as f64 * Self::HEADING_WEIGHT
+            + sighting.emphasized as f64 * Self::EMPHASIS_WEIGHT;
+        weighted / document_length.max(1) as f64 * idf
+    }
+}
+impl Default for TfIdf {
+    fn default() -> Self {
+        Self {
+            max_per_topic: 10,
+            min_score: 0.0,
+        }
+    }
+}
+impl KeywordStrategy for TfIdf {
+    #[allow(clippy::cast_precision_loss)]
+    fn select<'src>(
+        &self,
+        keywords: &[KeywordProperties<'src>],
+        documents: &HashMap<&'src Path, usize>,
+    ) -> Vec<KeywordProperties<'src>> {
+        let total = documents.len() as f64;
+
+        //
+        // Score every keyword in every document
+        let mut scores: HashMap<&Path, Vec<(f64, usize)>> = HashMap::new();
+        for (index, keyword) in keywords.iter().enumerate() {
+            //
+            // Smoothed, so words found in every document still score above zero
+            let idf = ((1.0 + total) / (1.0 + keyword.seen_in.len() as f64)).ln() + 1.0;
+            for sighting in &keyword.seen_in {
+                let length = documents.get(sighting.path).copied().unwrap_or_default();
+                let score = Self::score(sighting, length, idf);
+                if score > 0.0 && score >= self.min_score {
+                    scores
+                        .entry(sighting.path)
+                        .or_default()
+                        .push((score, index));
+                }
+            }
+        }
+
+        //
+        // Keep the best few of each document
+        let mut selected: HashMap<usize, Vec<&Path>> = HashMap::new();
+        for (path, mut document_scores) in scores {
+            document_scores.sort_by(|a, b| b.0.total_cmp(&a.0));
+            for (_, index) in document_scores.into_iter().take(self.max_per_topic) {
+                selected.entry(index).or_default().push(path);
+            }
+        }
+
+        selected
+            .into_iter()
+            .filter_map(|(index, paths)| {
+                keywords[index].filtered(|sighting| paths.contains(&sighting.path))
+            })
+            .collect()
+    }
+}
+
+/// Index words appearing in a limited number of documents
+///
+/// The default strategy, with a window of exactly one document
+#[derive(Debug, Clone)]
+pub struct DocumentFrequency {
+    /// Skip words found in fewer documents than this
+    pub min_documents: usize,
+
+    /// Skip words found in more documents than this
+    pub max_documents: usize,
+}
+impl Default for DocumentFrequency {
+    fn default() -> Self {
+        Self {
+            min_documents: 1,
+            max_documents: 1,
+        }
+    }
+}
+impl KeywordStrategy for DocumentFrequency {
+    fn select<'src>(
+        &self,
+        keywords: &[KeywordProperties<'src>],
+        _documents: &HashMap<&'src Path, usize>,
+    ) -> Vec<KeywordProperties<'src>> {
+        keywords
+            .iter()
+            .filter(|keyword| {
+                (self.min_documents..=self.max_documents).contains(&keyword.seen_in.len())
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Do not add any extracted keywords to the index
+#[derive(Debug, Clone, Default)]
+pub struct Off;
+impl KeywordStrategy for Off {
+    fn select<'src>(
+        &self,
+        _keywords: &[KeywordProperties<'src>],
+        _documents: &HashMap<&'src Path, usize>,
+    ) -> Vec<KeywordProperties<'src>> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chm::keyworder::{KeywordSettings, Keyworder};
+    use std::sync::Arc;
+
+    fn keywords(
+        strategy: impl KeywordStrategy + 'static,
+        documents: &[(&str, &str)],
+    ) -> Vec<String> {
+        let settings = KeywordSettings {
+            strategy: Arc::new(strategy),
+            ..KeywordSettings::default()
+        };
+        let mut keyworder = Keyworder::with_settings(settings);
+        for (path, html) in documents {
+            keyworder.process(Path::new(path), html);
+        }
+        keyworder
+            .visible_keywords()
+            .into_iter()
+            .map(|keyword| keyword.keyword)
+            .collect()
+    }
+
+    const DOCUMENTS: &[(&str, &str)] = &[
+        (
+            "a.html",
+            "<h1>Vectors</h1><p>growable arrays and Slices</p>",
+        ),
+        ("b.html", "<h1>Slices</h1><p>views into arrays</p>"),
+        ("c.html", "<h1>Strings</h1><p>text in arrays</p>"),
+    ];
+
+    #[test]
+    fn headings_only_keeps_heading_words() {
+        let found = keywords(HeadingsOnly { max_documents: 1 }, DOCUMENTS);
+        assert_eq!(found, ["Slices", "Strings", "Vectors"]);
+
+        let found = keywords(
+            HeadingsOnly { max_documents: 1 },
+            &[("a.html", "<h1>Shared</h1>"), ("b.html", "<h2>Shared</h2>")],
+        );
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn document_frequency_keeps_words_in_range() {
+        let strategy = DocumentFrequency {
+            min_documents: 1,
+            max_documents: 1,
+        };
+        let found = keywords(strategy, DOCUMENTS);
+        assert!(found.contains(&"growable".to_string()));
+        assert!(!found.contains(&"arrays".to_string()));
+        assert!(!found.contains(&"Slices".to_string()));
+
+        let strategy = DocumentFrequency {
+            min_documents: 2,
+            max_documents: 2,
+        };
+        assert_eq!(keywords(strategy, DOCUMENTS), ["Slices"]);
+
+        let strategy = DocumentFrequency {
+            min_documents: 3,
+            max_documents: 3,
+        };
+        assert_eq!(keywords(strategy, DOCUMENTS), ["arrays"]);
+    }
+
+    #[test]
+    fn off_keeps_nothing() {
+        assert!(keywords(Off, DOCUMENTS).is_empty());
+    }
+
+    #[test]
+    fn tf_idf_indexes_a_single_document() {
+        let strategy = TfIdf {
+            max_per_topic: 2,
+            min_score: 0.0,
+        };
+        let found = keywords(
+            strategy,
+            &[("a.html", "<h1>Borrowing</h1><p>rules of rules</p>")],
+        );
+        assert_eq!(found, ["Borrowing", "rules"]);
+    }
+
+    #[test]
+    fn tf_idf_ranks_rare_and_heading_words_first() {
+        let strategy = TfIdf {
+            max_per_topic: 1,
+            min_score: 0.0,
+        };
+        let found = keywords(
+            strategy,
+            &[
+                ("a.html", "<p>shared unique</p>"),
+                ("b.html", "<p>shared other</p><h2>Title</h2>"),
+            ],
+        );
+        assert_eq!(found, ["Title", "unique"]);
+    }
+}
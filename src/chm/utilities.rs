@@ -4,7 +4,7 @@ use std::path::{Path, PathBuf};
 /// Path normalization functions to clean up the code
 pub trait MakeAbsolute: AsRef<Path> {
     /// Append a relative path to the CWD
-    /// 
+    ///
     /// # Panics
     /// Panic if the CWD is missing
     fn make_absolute(&self) -> PathBuf {
@@ -38,7 +38,7 @@ pub trait SafeWrite: AsRef<Path> {
     }
 
     /// Write to a file, after creating the parent directories
-    /// 
+    ///
     /// # Errors
     /// Will return an error if the file cannot be created or written to
     fn safe_write(&self, content: &[u8]) -> std::io::Result<()> {
@@ -49,7 +49,7 @@ pub trait SafeWrite: AsRef<Path> {
     }
 
     /// Copy a file from one location to another, after creating the parent directories
-    /// 
+    ///
     /// # Errors
     /// Will return an error if the file cannot be created or written to, or the source
     /// cannot be read
@@ -69,6 +69,55 @@ pub trait SafeWrite: AsRef<Path> {
 impl SafeWrite for PathBuf {}
 impl SafeWrite for Path {}
 
+/// A relative URL from one file to another, both relative to the same root.\
+/// Either kind of slash is accepted in the paths
+#[must_use]
+pub fn relative_url(from: &Path, to: &Path) -> String {
+    fn parts(path: &Path) -> Vec<String> {
+        path.to_string_lossy()
+            .split(['/', '\\'])
+            .filter(|part| !part.is_empty() && *part != ".")
+            .map(str::to_string)
+            .collect()
+    }
+
+    let mut from = parts(from);
+    from.pop();
+    let to = parts(to);
+    let shared = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut url = vec!["..".to_string(); from.len() - shared];
+    url.extend_from_slice(&to[shared..]);
+    url.join("/")
+}
+
+/// Normalize a relative path into a `/` separated string, resolving `.` and `..`.\
+/// Either kind of slash is accepted. Returns `None` if the path leaves its root
+#[must_use]
+pub fn normalize_path(path: &Path) -> Option<String> {
+    let mut parts = vec![];
+    for part in path.to_string_lossy().split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part.to_string()),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// A short name for some content, stable between builds, used to name generated files.\
+/// This is the 64-bit FNV-1a hash, in hex
+#[must_use]
+pub fn content_hash(data: &[u8]) -> String {
+    let hash = data.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
 /// Escape HTML special chars in a string
 #[must_use]
 pub fn escape_html(text: &str) -> String {
@@ -87,8 +136,22 @@ pub fn escape_html(text: &str) -> String {
     buffer
 }
 
+/// Escape the ASCII punctuation in a string, so markdown shows it as plain text
+#[must_use]
+pub fn escape_markdown(text: &str) -> String {
+    let mut buffer = String::new();
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            buffer.push('\\');
+        }
+        buffer.push(c);
+    }
+
+    buffer
+}
+
 /// Locate a copy of the CHM compiler (hhc.exe)
-/// 
+///
 /// Searches in this order:
 /// - Current dir / path
 /// - `C:\\Program Files (x86)\\HTML Help Workshop\\hhc.exe`
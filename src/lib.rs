@@ -1,11 +1,23 @@
-//! If you need a copy of the CHM compiler, you can find one at:  
+//! If you need a copy of the CHM compiler, you can find one at:\
 //! <https://github.com/EWSoftware/SHFB/blob/master/ThirdPartyTools/htmlhelp.exe>
 //!
 //! Usage: install the binary, and include this `[output.chm]` in your `book.toml`
 //!
-//! These options are supported:
-//! - `language_code`: One of [`crate::chm::config::ChmLanguage`]. Default is `en-us`
-//! - `output_path`: filename for the result. Default is `book.chm`
+//! These options are supported, and unknown keys are reported as warnings:
+//! - `language-code`: One of [`crate::chm::config::ChmLanguage`]. Default is `en-us`
+//! - `output-path`: filename for the result. Default is `book.chm`
+//! - `popups`: path to a TOML file of text popups, see [`crate::chm::popups::ChmTextPopups::from_toml`]
+//! - `dead-anchors`: `warn` (default), `error` or `off` for links to missing headings
+//! - `part-titles`: `folder` (default) or `page` to also generate a page listing their chapters
+//! - `[output.chm.numbering]`: `placement` and `format` of section numbers
+//! - `[output.chm.index]`: the automatic index, see [`crate::chm::keyworder::KeywordStrategy`]
+//! - `[output.chm.related-topics]`: associative links, see [`crate::chm::alinks::RelatedTopics`]
+//! - `[output.chm.dependencies]`: folders files may be read from, and how symbolic links are followed
+//! - `[output.chm.highlight]`: code highlighting, see [`crate::chm::inputs::Highlighting`]
+//! - `[output.chm.math]`: math typesetting, with the `math` feature. See [`crate::chm::inputs::MathRendering`]
+//! - `[output.chm.diagrams]`: diagram rendering, with the `diagrams` feature. See [`crate::chm::inputs::DiagramRendering`]
+//! - `[output.chm.toc]`: TOC appearance, see [`crate::chm::hhc::ChmContentsProperties`]
+//! - `[output.chm.options]`: compiler settings, see [`crate::chm::hhp::ChmOptions`]
 #![warn(clippy::pedantic)]
 #![warn(missing_docs)]
 #![allow(clippy::missing_panics_doc)]
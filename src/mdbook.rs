@@ -1,13 +1,36 @@
 //! The mdbook part of this mdbook crate
 //!
 //! Contains a trait that lets you get CHM out of a mdbook context
-use crate::chm::{ChmBuilder, ChmLanguage, ChmTopicBuilder, utilities::MakeAbsolute};
+use crate::chm::{
+    ChmBuilder, ChmLanguage, ChmTopicBuilder,
+    alinks::RelatedTopics,
+    anchors::DeadAnchors,
+    curated::CuratedIndex,
+    hhc::{ChmContentsProperties, ChmEntryProperties},
+    hhk::{ChmIndexEntry, ChmIndexTopic},
+    hhp::ChmOptions,
+    inputs::{
+        Confinement, DiagramRendering, Highlighting, InputOptions, MathRendering, SymlinkPolicy,
+    },
+    keyworder::{
+        CodeEntries, CodeIndexMode, DocumentFrequency, HeadingEntries, HeadingsOnly,
+        KeywordSettings, KeywordStrategy, KeywordStrategyKind, MarkerMode, Off, Segmentation,
+        TfIdf,
+    },
+    popups::ChmTextPopups,
+    utilities::{MakeAbsolute, escape_html, escape_markdown, normalize_path},
+};
 use mdbook::{
     BookItem,
+    book::{Book, Chapter},
     preprocess::{LinkPreprocessor, Preprocessor, PreprocessorContext},
     renderer::RenderContext,
 };
-use std::{collections::HashSet, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 /// Get the current context from the command line arguments.
 #[must_use]
@@ -43,44 +66,172 @@ pub fn context() -> Option<RenderContext> {
 /// Trait to convert the current context to a CHM builder.
 pub trait MdBookChm {
     /// Get the current configuration options (default if unspecified)
-    fn chm_config(&self) -> MdbookChmConfig;
+    ///
+    /// # Errors
+    /// Will return an error if the `[output.chm]` table contains unknown keys or invalid values
+    fn chm_config(&self) -> std::io::Result<MdbookChmConfig>;
 
-    /// Return all chapters as chm topics that can be added to a CHM project
+    /// Return all chapters as chm topics that can be added to a CHM project, rendered with a configuration from [`Self::chm_config`]
     ///
     /// # Errors
     /// Will return an error if any files included or referenced cannot be read
-    fn topics(&self) -> std::io::Result<Vec<ChmTopicBuilder>>;
+    fn topics(&self, config: &MdbookChmConfig) -> std::io::Result<Vec<ChmTopicBuilder>>;
 
     /// Return the entire book and all content as a CHM project
     ///
     /// # Errors
-    /// Will return an error if any files included or referenced cannot be read
+    /// Will return an error if any files included or referenced cannot be read, or the configuration is invalid
     fn as_chm(&self) -> std::io::Result<ChmBuilder>;
 }
 impl MdBookChm for RenderContext {
-    fn chm_config(&self) -> MdbookChmConfig {
-        self.config
+    fn chm_config(&self) -> std::io::Result<MdbookChmConfig> {
+        let mut config: MdbookChmConfig = self
+            .config
             .get_deserialized_opt("output.chm")
-            .ok()
-            .flatten()
-            .unwrap_or_default()
+            .map(Option::unwrap_or_default)
+            .map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Invalid [output.chm] configuration: {e:#}"),
+                )
+            })?;
+
+        //
+        // Keys mdbook does not use are likely misspelled options
+        for key in config.unknown_keys() {
+            eprintln!("Warning: Unknown key `{key}` in [output.chm] is ignored");
+        }
+
+        if config.numbering.placement.is_none() {
+            //
+            // Match the HTML renderer, unless told otherwise
+            let no_labels = self
+                .config
+                .get("output.html.no-section-label")
+                .and_then(toml::Value::as_bool)
+                .unwrap_or_default();
+            config.numbering.placement = Some(if no_labels {
+                NumberPlacement::None
+            } else {
+                NumberPlacement::Toc
+            });
+        }
+
+        config.highlight.validate()?;
+        Ok(config)
     }
 
-    fn topics(&self) -> std::io::Result<Vec<ChmTopicBuilder>> {
+    fn topics(&self, config: &MdbookChmConfig) -> std::io::Result<Vec<ChmTopicBuilder>> {
         let mut visited_topics = HashSet::new();
         let mut topics = Vec::new();
 
-        for item in self.book.iter() {
-            if let Some(topic) = item.as_topic(&mut visited_topics) {
-                topics.push(topic?);
+        //
+        // Links between chapters are checked against the chapters of the book
+        let chapters = self
+            .book
+            .iter()
+            .filter_map(|item| match item {
+                BookItem::Chapter(chapter) => chapter.source_path.as_ref(),
+                _ => None,
+            })
+            .filter_map(|path| normalize_path(&Path::new("src").join(path)))
+            .collect();
+
+        //
+        // Hidden lines in code blocks follow the HTML renderer
+        let hidden_lines = self
+            .config
+            .get_deserialized_opt("output.html.code.hidelines")
+            .map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Invalid [output.html.code.hidelines] configuration: {e:#}"),
+                )
+            })?
+            .unwrap_or_default();
+
+        //
+        // Math and diagrams are only rendered with their cargo features
+        for (table, feature) in [
+            ("math", cfg!(feature = "math")),
+            ("diagrams", cfg!(feature = "diagrams")),
+        ] {
+            if !feature && self.config.get(&format!("output.chm.{table}")).is_some() {
+                eprintln!(
+                    "Warning: [output.chm.{table}] is set, but mdbook-chm was built without the `{table}` feature"
+                );
+            }
+        }
+
+        //
+        // Rendered diagrams are kept beside the output, unless told otherwise
+        let mut diagrams = config.diagrams.clone();
+        diagrams.cache = Some(match &config.diagrams.cache {
+            Some(cache) => self.root.join(cache),
+            None => self.root.join(&self.destination).join("cache"),
+        });
+
+        //
+        // Options shared by every chapter
+        let options = InputOptions {
+            first_heading_format: None,
+            code_identifiers: config.index.code != CodeIndexMode::Off,
+            chapters: Some(Arc::new(chapters)),
+            source_root: Some(PathBuf::from("src")),
+            confinement: Some(Confinement::new(
+                self.root.make_absolute(),
+                &config.dependencies.allow,
+                config.dependencies.symlinks,
+            )),
+            highlighting: config.highlight.clone(),
+            hidden_lines,
+            math: config.math.clone(),
+            diagrams,
+        };
+
+        //
+        // Numbered chapters following a part title become children of that part
+        let mut part: Option<PartTopic> = None;
+        let mut part_count = 0;
+        for item in &self.book.sections {
+            let in_part = match item {
+                BookItem::PartTitle(title) => {
+                    if let Some(part) = part.take() {
+                        topics.push(part.finish(config, &options)?);
+                    }
+
+                    part_count += 1;
+                    part = Some(PartTopic::new(title, part_count));
+                    continue;
+                }
+                BookItem::Chapter(chapter) => chapter.number.is_some(),
+                BookItem::Separator => true,
+            };
+
+            let Some(topic) = item.as_topic(config, &options, &mut visited_topics) else {
+                continue;
+            };
+            let topic = topic?;
+
+            match &mut part {
+                Some(part) if in_part => part.push(item, topic),
+                _ => {
+                    if let Some(part) = part.take() {
+                        topics.push(part.finish(config, &options)?);
+                    }
+                    topics.push(topic);
+                }
             }
         }
+        if let Some(part) = part.take() {
+            topics.push(part.finish(config, &options)?);
+        }
 
         Ok(topics)
     }
 
     fn as_chm(&self) -> std::io::Result<ChmBuilder> {
-        let config = self.chm_config();
+        let config = self.chm_config()?;
         let title = self.config.book.title.as_deref().unwrap_or("Book");
 
         //
@@ -93,10 +244,58 @@ impl MdBookChm for RenderContext {
         let output_path = root.join(&config.output_path);
 
         let mut builder = ChmBuilder::new(title, lang, output_path);
+        builder.with_options(config.options.clone());
+        builder.with_contents_properties(config.toc.properties.clone());
+        builder.with_keyword_settings(config.index.keyword_settings(lang)?);
+        builder.with_related_topics(config.related_topics.clone());
+        builder.with_dead_anchors(config.dead_anchors);
+        builder.with_confinement(Confinement::new(
+            self.root.make_absolute(),
+            &config.dependencies.allow,
+            config.dependencies.symlinks,
+        ));
+
+        //
+        // Add text popups
+        if let Some(popups_path) = &config.popups {
+            let source = std::fs::read_to_string(popups_path).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("Could not read `{popups_path}`: {e}"))
+            })?;
+            let popups = ChmTextPopups::from_toml(&source).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("Invalid popups in `{popups_path}`: {e}"))
+            })?;
+            for popup in popups.0 {
+                builder.with_popup(popup);
+            }
+        }
+
+        //
+        // Add the curated index
+        if let Some(index_path) = &config.index.file {
+            let source = std::fs::read_to_string(index_path).map_err(|e| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!("Could not read `{}`: {e}", index_path.display()),
+                )
+            })?;
+            let curated = CuratedIndex::from_toml(&source).map_err(|e| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!("Invalid index in `{}`: {e}", index_path.display()),
+                )
+            })?;
+
+            for entry in curated_entries(&self.book, &config.numbering, &curated, index_path)? {
+                builder.with_index_entry(entry);
+            }
+            for keyword in &curated.blocklist {
+                builder.with_blocked_keyword(keyword);
+            }
+        }
 
         //
         // Add topics
-        for topic in self.topics()? {
+        for topic in self.topics(&config)? {
             builder.with_contents(topic);
         }
 
@@ -104,41 +303,130 @@ impl MdBookChm for RenderContext {
     }
 }
 
+/// Turns the terms of a curated index into index entries, pointing at the chapters of the book
+fn curated_entries(
+    book: &Book,
+    numbering: &NumberingConfig,
+    curated: &CuratedIndex,
+    index_path: &Path,
+) -> std::io::Result<Vec<ChmIndexEntry>> {
+    let chapters = book
+        .iter()
+        .filter_map(|item| match item {
+            BookItem::Chapter(chapter) => Some((chapter.source_path.as_deref()?, chapter)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut entries = vec![];
+    for keyword in &curated.keywords {
+        let mut topics = vec![];
+        for target in &keyword.targets {
+            let (target_path, anchor) = match target.split_once('#') {
+                Some((path, anchor)) => (path, Some(anchor)),
+                None => (target.as_str(), None),
+            };
+            let Some(chapter) = chapters.get(Path::new(target_path)) else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "Index term `{}` in `{}` points at `{target}`, which is not a chapter in the book",
+                        keyword.terms.join(" > "),
+                        index_path.display()
+                    ),
+                ));
+            };
+
+            let mut file = Path::new("src")
+                .join(Path::new(target_path).with_extension("html"))
+                .to_windows_path();
+            if let Some(anchor) = anchor {
+                file = format!("{file}#{anchor}");
+            }
+            topics.push(ChmIndexTopic::new(
+                &escape_html(&numbering.toc_title(chapter)),
+                &escape_html(&file),
+            ));
+        }
+
+        for terms in std::iter::once(&keyword.terms).chain(&keyword.synonyms) {
+            let Some((last, parents)) = terms.split_last() else {
+                continue;
+            };
+            let entry = ChmIndexEntry {
+                keyword: escape_html(last),
+                topics: topics.clone(),
+                see_also: keyword.see_also.as_deref().map(escape_html),
+                children: vec![],
+            };
+            let parents = parents
+                .iter()
+                .map(|parent| escape_html(parent))
+                .collect::<Vec<_>>();
+            entries.push(entry.with_parents(&parents));
+        }
+    }
+
+    Ok(entries)
+}
+
 trait AsTopic {
     fn as_topic<'a>(
         &'a self,
+        config: &MdbookChmConfig,
+        options: &InputOptions,
         visited_topics: &mut HashSet<&'a Path>,
     ) -> Option<std::io::Result<ChmTopicBuilder>>;
 }
 impl AsTopic for mdbook::BookItem {
     fn as_topic<'a>(
         &'a self,
+        config: &MdbookChmConfig,
+        options: &InputOptions,
         visited_topics: &mut HashSet<&'a Path>,
     ) -> Option<std::io::Result<ChmTopicBuilder>> {
-        let BookItem::Chapter(chapter) = self else {
-            return None;
+        let chapter = match self {
+            BookItem::Chapter(chapter) => chapter,
+            BookItem::Separator => return Some(Ok(ChmTopicBuilder::new_separator())),
+            BookItem::PartTitle(title) => return Some(Ok(ChmTopicBuilder::new_folder(title))),
         };
 
-        let chapter_path = chapter.source_path.as_ref()?;
+        //
+        // Draft chapters have no page, but still hold their children
+        let mut topic = if let Some(chapter_path) = chapter.source_path.as_ref() {
+            if visited_topics.contains(chapter_path.as_path()) {
+                return None;
+            }
 
-        if visited_topics.contains(chapter_path.as_path()) {
-            return None;
-        }
+            visited_topics.insert(chapter_path);
 
-        visited_topics.insert(chapter_path);
+            println!("Adding topic: {}", chapter_path.display());
 
-        println!("Adding topic: {}", chapter_path.display());
+            let options = InputOptions {
+                first_heading_format: config.numbering.heading_format(chapter),
+                ..options.clone()
+            };
+            let mut topic = match ChmTopicBuilder::new_with_options(
+                &config.numbering.toc_title(chapter),
+                Path::new("src").join(chapter_path.to_windows_path()),
+                &chapter.content,
+                options,
+            ) {
+                Ok(topic) => topic,
+                Err(e) => return Some(Err(e)),
+            };
+            topic.with_properties(config.toc.entry_properties(chapter_path));
+            topic
+        } else {
+            println!("Adding draft topic: {}", chapter.name);
 
-        let mut topic = match ChmTopicBuilder::new_with_content(
-            &chapter.name,
-            Path::new("src").join(chapter_path.to_windows_path()),
-            &chapter.content,
-        ) {
-            Ok(topic) => topic,
-            Err(e) => return Some(Err(e)),
+            let mut topic = ChmTopicBuilder::new_folder(&config.numbering.toc_title(chapter));
+            topic.with_properties(config.toc.defaults.clone());
+            topic
         };
+
         for item in &chapter.sub_items {
-            if let Some(subtopic) = item.as_topic(visited_topics) {
+            if let Some(subtopic) = item.as_topic(config, options, visited_topics) {
                 match subtopic {
                     Ok(subtopic) => {
                         topic.with_child(subtopic);
@@ -154,18 +442,745 @@ impl AsTopic for mdbook::BookItem {
     }
 }
 
+/// A part title, and the numbered chapters that follow it
+struct PartTopic<'a> {
+    title: &'a str,
+    number: usize,
+    chapters: Vec<&'a Chapter>,
+    children: Vec<ChmTopicBuilder>,
+}
+impl<'a> PartTopic<'a> {
+    fn new(title: &'a str, number: usize) -> Self {
+        Self {
+            title,
+            number,
+            chapters: vec![],
+            children: vec![],
+        }
+    }
+
+    fn push(&mut self, item: &'a BookItem, topic: ChmTopicBuilder) {
+        if let BookItem::Chapter(chapter) = item {
+            self.chapters.push(chapter);
+        }
+        self.children.push(topic);
+    }
+
+    /// The part's TOC entry. Its page is rendered like the chapters, with the same options
+    fn finish(
+        self,
+        config: &MdbookChmConfig,
+        options: &InputOptions,
+    ) -> std::io::Result<ChmTopicBuilder> {
+        println!("Adding part: {}", self.title);
+
+        let mut topic = match config.part_titles {
+            PartTitles::Folder => ChmTopicBuilder::new_folder(&self.title),
+            PartTitles::Page => ChmTopicBuilder::new_with_options(
+                &self.title,
+                self.page_path(options.chapters.as_deref().unwrap_or(&HashSet::new()))?,
+                &self.overview(&config.numbering),
+                options.clone(),
+            )?,
+        };
+        topic.with_properties(config.toc.defaults.clone());
+
+        for child in self.children {
+            topic.with_child(child);
+        }
+        Ok(topic)
+    }
+
+    /// The path of the generated page, beside the chapters.\
+    /// The name is one chapters are unlikely to use, and the build fails if one does, rather than overwrite it
+    fn page_path(&self, chapters: &HashSet<String>) -> std::io::Result<PathBuf> {
+        let name = format!("_part-{}.md", self.number);
+        if chapters.contains(&format!("src/{name}")) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "The page generated for part `{}` would overwrite the chapter `{name}`",
+                    self.title
+                ),
+            ));
+        }
+        Ok(Path::new("src").join(name))
+    }
+
+    /// Generates a page listing the chapters in the part, titled as they are in the TOC
+    fn overview(&self, numbering: &NumberingConfig) -> String {
+        fn list(
+            chapters: &[&Chapter],
+            numbering: &NumberingConfig,
+            depth: usize,
+            lines: &mut Vec<String>,
+        ) {
+            for chapter in chapters {
+                let indent = "    ".repeat(depth);
+                let title = escape_markdown(&numbering.toc_title(chapter));
+                match &chapter.source_path {
+                    Some(path) => lines.push(format!(
+                        "{indent}- [{title}](<{}>)",
+                        path.to_string_lossy()
+                            .replace('\\', "/")
+                            .replace('<', "\\<")
+                            .replace('>', "\\>")
+                    )),
+                    None => lines.push(format!("{indent}- {title}")),
+                }
+
+                let children = chapter
+                    .sub_items
+                    .iter()
+                    .filter_map(|item| match item {
+                        BookItem::Chapter(chapter) => Some(chapter),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                list(&children, numbering, depth + 1, lines);
+            }
+        }
+
+        let mut lines = vec![format!("# {}", escape_markdown(self.title)), String::new()];
+        list(&self.chapters, numbering, 0, &mut lines);
+        lines.join("\n")
+    }
+}
+
+/// The `[output.chm.numbering]` table
+///
+/// Controls where chapter section numbers (like `1.2.3`) are shown
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct NumberingConfig {
+    /// Where to show the numbers. Follows `output.html.no-section-label` if unset
+    placement: Option<NumberPlacement>,
+
+    /// How to combine the number with the title. Supports `{number}` and `{title}`
+    format: String,
+}
+impl NumberingConfig {
+    /// Apply the format for a chapter, if it has a number
+    fn apply(&self, chapter: &Chapter, title: &str) -> Option<String> {
+        let number = chapter.number.as_ref()?;
+        let number = number
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(".");
+
+        let mut label = self.format.replace("{number}", &number);
+        if !label.contains("{title}") {
+            label.push_str("{title}");
+        }
+        Some(label.replace("{title}", title))
+    }
+
+    fn toc_title(&self, chapter: &Chapter) -> String {
+        match self.placement {
+            Some(NumberPlacement::Toc | NumberPlacement::Both) => self
+                .apply(chapter, &chapter.name)
+                .unwrap_or_else(|| chapter.name.clone()),
+            _ => chapter.name.clone(),
+        }
+    }
+
+    fn heading_format(&self, chapter: &Chapter) -> Option<String> {
+        match self.placement {
+            Some(NumberPlacement::Heading | NumberPlacement::Both) => {
+                self.apply(chapter, "{title}")
+            }
+            _ => None,
+        }
+    }
+}
+impl Default for NumberingConfig {
+    fn default() -> Self {
+        Self {
+            placement: None,
+            format: "{number}. {title}".to_string(),
+        }
+    }
+}
+
+/// The `[output.chm.index]` table
+///
+/// Selects the [`crate::chm::keyworder::KeywordStrategy`] used for the automatic index, and its thresholds.
+/// Unset thresholds use the defaults of the selected strategy
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+struct IndexConfig {
+    strategy: KeywordStrategyKind,
+    min_length: usize,
+    max_per_topic: Option<usize>,
+    min_score: Option<f64>,
+    min_documents: Option<usize>,
+    max_documents: Option<usize>,
+    builtin_stop_words: bool,
+    stop_words: Vec<PathBuf>,
+    case_folding: bool,
+    stemming: bool,
+    segmentation: Option<Segmentation>,
+    markers: MarkerMode,
+    file: Option<PathBuf>,
+    heading_level: u8,
+    nest_headings: bool,
+    exclude_headings: Vec<String>,
+    code: CodeIndexMode,
+    code_group: String,
+}
+impl IndexConfig {
+    fn keyword_settings(&self, language: ChmLanguage) -> std::io::Result<KeywordSettings> {
+        let strategy: Arc<dyn KeywordStrategy> = match self.strategy {
+            KeywordStrategyKind::HeadingsOnly => {
+                let defaults = HeadingsOnly::default();
+                Arc::new(HeadingsOnly {
+                    max_documents: self.max_documents.unwrap_or(defaults.max_documents),
+                })
+            }
+            KeywordStrategyKind::TfIdf => {
+                let defaults = TfIdf::default();
+                Arc::new(TfIdf {
+                    max_per_topic: self.max_per_topic.unwrap_or(defaults.max_per_topic),
+                    min_score: self.min_score.unwrap_or(defaults.min_score),
+                })
+            }
+            KeywordStrategyKind::DocumentFrequency => {
+                let defaults = DocumentFrequency::default();
+                Arc::new(DocumentFrequency {
+                    min_documents: self.min_documents.unwrap_or(defaults.min_documents),
+                    max_documents: self.max_documents.unwrap_or(defaults.max_documents),
+                })
+            }
+            KeywordStrategyKind::Off => Arc::new(Off),
+        };
+
+        let mut settings = KeywordSettings::for_language(language);
+        settings.strategy = strategy;
+        settings.min_length = self.min_length;
+        settings.case_folding = self.case_folding;
+        settings.stemming = self.stemming;
+        settings.markers = self.markers;
+        settings.code = CodeEntries {
+            mode: self.code,
+            group: self.code_group.clone(),
+        };
+        settings.headings = HeadingEntries {
+            max_level: self.heading_level,
+            nested: self.nest_headings,
+            exclude: self
+                .exclude_headings
+                .iter()
+                .map(|heading| match heading.split_once('#') {
+                    //
+                    // `chapter.md#id` becomes the path of the topic
+                    Some((chapter, id)) => format!(
+                        "{}#{id}",
+                        Path::new("src")
+                            .join(Path::new(chapter).with_extension("html"))
+                            .to_windows_path()
+                    ),
+                    None => heading.clone(),
+                })
+                .map(|heading| heading.to_lowercase())
+                .collect(),
+        };
+        if let Some(segmentation) = self.segmentation {
+            settings.segmentation = segmentation;
+        }
+        if !self.builtin_stop_words {
+            settings.stop_words.clear();
+        }
+
+        //
+        // Stop word files hold whitespace separated words, with `#` starting a comment
+        for path in &self.stop_words {
+            let source = std::fs::read_to_string(path).map_err(|e| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!("Could not read stop words from `{}`: {e}", path.display()),
+                )
+            })?;
+            for line in source.lines() {
+                let line = line.split('#').next().unwrap_or_default();
+                settings
+                    .stop_words
+                    .extend(line.split_whitespace().map(str::to_lowercase));
+            }
+        }
+
+        Ok(settings)
+    }
+}
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            strategy: KeywordStrategyKind::default(),
+            min_length: 1,
+            max_per_topic: None,
+            min_score: None,
+            min_documents: None,
+            max_documents: None,
+            builtin_stop_words: false,
+            stop_words: vec![],
+            case_folding: false,
+            stemming: false,
+            segmentation: None,
+            markers: MarkerMode::default(),
+            file: None,
+            heading_level: 3,
+            nest_headings: false,
+            exclude_headings: vec![],
+            code: CodeIndexMode::default(),
+            code_group: CodeEntries::default().group,
+        }
+    }
+}
+
+/// Where chapter section numbers are shown
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum NumberPlacement {
+    /// Nowhere
+    None,
+
+    /// Before the TOC titles
+    Toc,
+
+    /// Before the first heading of each topic
+    Heading,
+
+    /// Both in the TOC and the topics
+    Both,
+}
+
+/// How part titles are shown in the TOC
+#[derive(Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum PartTitles {
+    /// A folder without a page
+    #[default]
+    Folder,
+
+    /// A folder with a generated page listing its chapters
+    Page,
+}
+
 /// Configuration structure for the rendering
+///
+/// The table does not deny unknown fields, since mdbook keeps `command` and `optional` in it.
+/// Other keys no option claimed are kept, and reported as warnings
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct MdbookChmConfig {
     language_code: String,
     output_path: String,
+    popups: Option<String>,
+    part_titles: PartTitles,
+    numbering: NumberingConfig,
+    index: IndexConfig,
+    related_topics: RelatedTopics,
+    dead_anchors: DeadAnchors,
+    dependencies: DependencyConfig,
+    highlight: Highlighting,
+    math: MathRendering,
+    diagrams: DiagramRendering,
+    options: ChmOptions,
+    toc: TocConfig,
+
+    #[serde(flatten, skip_serializing)]
+    unknown: BTreeMap<String, toml::Value>,
 }
 impl Default for MdbookChmConfig {
     fn default() -> Self {
         Self {
             language_code: "en-us".to_string(),
             output_path: "book.chm".to_string(),
+            popups: None,
+            part_titles: PartTitles::default(),
+            numbering: NumberingConfig::default(),
+            index: IndexConfig::default(),
+            related_topics: RelatedTopics::default(),
+            dead_anchors: DeadAnchors::default(),
+            dependencies: DependencyConfig::default(),
+            highlight: Highlighting::default(),
+            math: MathRendering::default(),
+            diagrams: DiagramRendering::default(),
+            options: ChmOptions::default(),
+            toc: TocConfig::default(),
+            unknown: BTreeMap::new(),
+        }
+    }
+}
+impl MdbookChmConfig {
+    /// Keys of the `[output.chm]` table that are neither options nor used by mdbook itself
+    fn unknown_keys(&self) -> Vec<&str> {
+        self.unknown
+            .keys()
+            .map(String::as_str)
+            .filter(|key| !matches!(*key, "command" | "optional"))
+            .collect()
+    }
+}
+
+/// The `[output.chm.dependencies]` table
+///
+/// Dependencies are confined to the book, except for the `allow`ed directories
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct DependencyConfig {
+    allow: Vec<PathBuf>,
+    symlinks: SymlinkPolicy,
+}
+
+/// The `[output.chm.toc]` table
+///
+/// Holds the TOC pane properties, the default properties of every entry,
+/// and per-chapter overrides in `[output.chm.toc.chapters."path/to/chapter.md"]`
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case", try_from = "TocTable")]
+struct TocConfig {
+    #[serde(flatten)]
+    properties: ChmContentsProperties,
+
+    #[serde(flatten)]
+    defaults: ChmEntryProperties,
+
+    chapters: HashMap<PathBuf, ChmEntryProperties>,
+}
+
+/// The `[output.chm.toc]` table as written, keeping the keys no setting claimed.\
+/// `deny_unknown_fields` does not work with flattened fields, so they are rejected in [`TocConfig::try_from`]
+#[derive(serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct TocTable {
+    #[serde(flatten)]
+    properties: ChmContentsProperties,
+
+    #[serde(flatten)]
+    defaults: ChmEntryProperties,
+
+    chapters: HashMap<PathBuf, ChmEntryProperties>,
+
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+impl Default for TocTable {
+    fn default() -> Self {
+        let TocConfig {
+            properties,
+            defaults,
+            chapters,
+        } = TocConfig::default();
+        Self {
+            properties,
+            defaults,
+            chapters,
+            unknown: BTreeMap::new(),
+        }
+    }
+}
+impl TryFrom<TocTable> for TocConfig {
+    type Error = String;
+
+    fn try_from(table: TocTable) -> Result<Self, Self::Error> {
+        if let Some(key) = table.unknown.keys().next() {
+            return Err(format!("unknown field `{key}` in [output.chm.toc]"));
         }
+        Ok(Self {
+            properties: table.properties,
+            defaults: table.defaults,
+            chapters: table.chapters,
+        })
+    }
+}
+impl TocConfig {
+    fn entry_properties(&self, chapter_path: &Path) -> ChmEntryProperties {
+        match self.chapters.get(chapter_path) {
+            Some(overrides) => self.defaults.merged(overrides),
+            None => self.defaults.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chm::{hhc::ChmContentsEntry, hhk::ChmIndex};
+
+    #[test]
+    fn part_pages_do_not_overwrite_chapters() {
+        let part = PartTopic::new("Guide", 1);
+
+        let chapters = HashSet::from(["src/part-1.md".to_string()]);
+        assert_eq!(
+            part.page_path(&chapters).unwrap(),
+            Path::new("src").join("_part-1.md")
+        );
+
+        let chapters = HashSet::from(["src/_part-1.md".to_string()]);
+        let error = part.page_path(&chapters).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn part_pages_escape_and_link_their_chapters() {
+        let mut chapter = Chapter::new(
+            "*Vec* [or] <b>Box</b>",
+            String::new(),
+            "guide/vec.md",
+            vec![],
+        );
+        chapter.number = Some(mdbook::book::SectionNumber(vec![2]));
+        let mut part = PartTopic::new("C# & <Rust>", 1);
+        part.chapters.push(&chapter);
+
+        let mut config = MdbookChmConfig {
+            part_titles: PartTitles::Page,
+            ..MdbookChmConfig::default()
+        };
+        config.numbering.placement = Some(NumberPlacement::Toc);
+        let options = InputOptions {
+            chapters: Some(Arc::new(HashSet::from(["src/guide/vec.md".to_string()]))),
+            ..InputOptions::default()
+        };
+
+        let entry = ChmContentsEntry::from(part.finish(&config, &options).unwrap());
+        let html = String::from_utf8(entry.files.last().unwrap().contents.clone()).unwrap();
+        assert!(html.contains("C# &amp; &lt;Rust&gt;</h1>"));
+        assert!(html.contains(
+            r#"<li><a href="guide/vec.html">2. *Vec* [or] &lt;b&gt;Box&lt;/b&gt;</a></li>"#
+        ));
+    }
+
+    #[test]
+    fn toc_rejects_unknown_keys() {
+        let toc = |source: &str| toml::from_str::<TocConfig>(source);
+
+        let config =
+            toc("lines = true\nimage-number = 3\n[chapters.\"a.md\"]\nnew = true").unwrap();
+        assert!(config.properties.lines);
+        assert_eq!(config.defaults.image_number, Some(3));
+        assert_eq!(config.chapters[Path::new("a.md")].new, Some(true));
+
+        let error = toc("plus-minsu = false").unwrap_err();
+        assert!(error.to_string().contains("unknown field `plus-minsu`"));
+        assert!(toc("[chapters.\"a.md\"]\nimage-numbr = 3").is_err());
+    }
+
+    #[test]
+    fn unknown_top_level_keys_are_reported() {
+        let config: MdbookChmConfig = toml::from_str(
+            "command = \"mdbook-chm\"\noptional = true\npart-titles = \"page\"\npart_titles = \"page\"\npopups = \"popups.toml\"\n[numbering]\n",
+        )
+        .unwrap();
+        assert_eq!(config.unknown_keys(), vec!["part_titles"]);
+        assert!(matches!(config.part_titles, PartTitles::Page));
+
+        //
+        // Options left unset are known too
+        let config: MdbookChmConfig = toml::from_str(
+            "[math]\nfont-size = 10.5\n[diagrams]\ncache = \"cache\"\n[toc]\nlines = true\n[index]\nmin-length = 2",
+        )
+        .unwrap();
+        assert!(config.popups.is_none());
+        assert!((config.math.font_size - 10.5).abs() < f64::EPSILON);
+        assert!(config.toc.properties.lines);
+        assert_eq!(config.index.min_length, 2);
+        assert!(config.unknown_keys().is_empty());
+
+        let config = context("[output.chm]\nlanguage_code = \"fr\"", Book::new())
+            .chm_config()
+            .unwrap();
+        assert_eq!(config.unknown_keys(), vec!["language_code"]);
+        assert_eq!(config.language_code, "en-us");
+    }
+
+    /// A render context for a book with the given `book.toml`
+    fn context(book_toml: &str, book: Book) -> RenderContext {
+        let config = book_toml.parse::<mdbook::Config>().unwrap();
+        RenderContext::new(".", book, config, "book")
+    }
+
+    fn numbered_chapter(name: &str, number: &[u32]) -> Chapter {
+        let mut chapter = Chapter::new(name, format!("# {name}\n"), "intro.md", vec![]);
+        chapter.number = Some(mdbook::book::SectionNumber(number.to_vec()));
+        chapter
+    }
+
+    #[test]
+    fn numbering_follows_the_html_renderer() {
+        let config = context("", Book::new()).chm_config().unwrap();
+        assert!(matches!(
+            config.numbering.placement,
+            Some(NumberPlacement::Toc)
+        ));
+
+        let config = context("[output.html]\nno-section-label = true", Book::new())
+            .chm_config()
+            .unwrap();
+        assert!(matches!(
+            config.numbering.placement,
+            Some(NumberPlacement::None)
+        ));
+
+        let config = context(
+            "[output.html]\nno-section-label = true\n[output.chm.numbering]\nplacement = \"both\"",
+            Book::new(),
+        )
+        .chm_config()
+        .unwrap();
+        assert!(matches!(
+            config.numbering.placement,
+            Some(NumberPlacement::Both)
+        ));
+
+        let error = context("[output.chm.numbering]\nplacment = \"toc\"", Book::new())
+            .chm_config()
+            .unwrap_err();
+        assert!(error.to_string().contains("unknown field `placment`"));
+    }
+
+    #[test]
+    fn numbers_prefix_titles_and_headings() {
+        let chapter = numbered_chapter("Intro", &[1, 2]);
+        let mut unnumbered = chapter.clone();
+        unnumbered.number = None;
+
+        let mut numbering = NumberingConfig {
+            placement: Some(NumberPlacement::Toc),
+            ..NumberingConfig::default()
+        };
+        assert_eq!(numbering.toc_title(&chapter), "1.2. Intro");
+        assert_eq!(numbering.toc_title(&unnumbered), "Intro");
+        assert_eq!(numbering.heading_format(&chapter), None);
+
+        numbering.placement = Some(NumberPlacement::Heading);
+        numbering.format = "Section {number}: ".to_string();
+        assert_eq!(numbering.toc_title(&chapter), "Intro");
+        assert_eq!(
+            numbering.heading_format(&chapter).as_deref(),
+            Some("Section 1.2: {title}")
+        );
+
+        let options = InputOptions {
+            first_heading_format: numbering.heading_format(&chapter),
+            ..InputOptions::default()
+        };
+        let topic = ChmTopicBuilder::new_with_options(
+            &numbering.toc_title(&chapter),
+            "src/intro.md",
+            &chapter.content,
+            options,
+        )
+        .unwrap();
+        let entry = ChmContentsEntry::from(topic);
+        let html = String::from_utf8(entry.files.last().unwrap().contents.clone()).unwrap();
+        assert!(html.contains(r#"<a name="intro" id="intro"></a>Section 1.2: Intro</h1>"#));
+    }
+
+    #[test]
+    fn numbered_titles_are_escaped_in_the_toc() {
+        let chapter = numbered_chapter("Traits & <Generics>", &[3]);
+        let numbering = NumberingConfig {
+            placement: Some(NumberPlacement::Toc),
+            ..NumberingConfig::default()
+        };
+        let topic = ChmTopicBuilder::new_with_options(
+            &numbering.toc_title(&chapter),
+            "src/intro.md",
+            &chapter.content,
+            InputOptions::default(),
+        )
+        .unwrap();
+
+        let entry = ChmContentsEntry::from(topic).format(1);
+        assert!(entry.contains(r#"<param name="Name" value="3. Traits &amp; &lt;Generics&gt;">"#));
+    }
+
+    #[test]
+    fn curated_terms_point_at_chapters() {
+        let config = context("[output.chm.index]\nfile = \"index.toml\"", Book::new())
+            .chm_config()
+            .unwrap();
+        assert_eq!(config.index.file.as_deref(), Some(Path::new("index.toml")));
+
+        let mut book = Book::new();
+        book.push_item(numbered_chapter("Intro", &[1]));
+        let curated = CuratedIndex::from_toml(
+            r#"
+            [keywords]
+            "Vec > push" = { targets = ["intro.md#push"], synonyms = ["Append"] }
+            "Lifetimes" = { see-also = "Borrowing" }
+            "#,
+        )
+        .unwrap();
+        let numbering = NumberingConfig {
+            placement: Some(NumberPlacement::Toc),
+            ..NumberingConfig::default()
+        };
+
+        let mut index = ChmIndex::new();
+        index
+            .extend(curated_entries(&book, &numbering, &curated, Path::new("index.toml")).unwrap());
+        assert!(index.to_string().contains(
+            r#"<param name="Name" value="Lifetimes">        <param name="See Also" value="Borrowing">"#
+        ));
+        index.remove_keywords(&HashSet::from(["lifetimes".to_string()]));
+        let index = index.to_string();
+
+        assert!(index.contains(r#"<param name="Name" value="Vec">"#));
+        assert!(index.contains(
+            r#"<param name="Name" value="push">        <param name="Local" value="src\intro.html#push">"#
+        ));
+        assert!(index.contains(
+            r#"<param name="Name" value="Append">        <param name="Local" value="src\intro.html#push">"#
+        ));
+        assert!(!index.contains("Lifetimes"));
+
+        let curated = CuratedIndex::from_toml("[keywords]\nVec = \"missing.md\"").unwrap();
+        let error =
+            curated_entries(&book, &numbering, &curated, Path::new("index.toml")).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("`missing.md`, which is not a chapter")
+        );
+    }
+
+    #[test]
+    fn heading_settings_point_at_topics() {
+        let config = context(
+            "[output.chm.index]\nheading-level = 2\nnest-headings = true\nexclude-headings = [\"Summary\", \"guide/vec.md#Notes\"]",
+            Book::new(),
+        )
+        .chm_config()
+        .unwrap();
+        let settings = config
+            .index
+            .keyword_settings(ChmLanguage::default())
+            .unwrap();
+        assert_eq!(settings.headings.max_level, 2);
+        assert!(settings.headings.nested);
+        assert_eq!(
+            settings.headings.exclude,
+            [
+                "src\\guide\\vec.html#notes".to_string(),
+                "summary".to_string()
+            ]
+            .into()
+        );
+    }
+
+    #[test]
+    fn index_defaults_keep_every_word() {
+        let settings = IndexConfig::default()
+            .keyword_settings(ChmLanguage::default())
+            .unwrap();
+        assert_eq!(settings.min_length, 1);
+        assert!(!settings.case_folding);
+        assert!(!settings.stemming);
+        assert!(settings.stop_words.is_empty());
     }
 }